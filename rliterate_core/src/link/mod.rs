@@ -20,7 +20,7 @@
  */
 
 use parser;
-use parser::{LitFile, Block, BlockModifier};
+use parser::{LitFile, Block, BlockModifier, ExpansionMode};
 
 use std::collections::{HashMap, VecDeque};
 use std::path::{PathBuf};
@@ -32,7 +32,12 @@ peg::parser!{grammar grammar() for str {
 
     rule _ = whitespace()+
 
-    rule name() = ((!link_end() non_whitespace())+) ++ _
+    rule arg_separator() = "---"
+
+    rule word() = (!link_end() non_whitespace())+
+
+    // Text after a `---` is part of the name, unless it's an expansion mode
+    rule name() = word() (!mode() _ word())*
 
     rule link_start() = "@{"
     rule link_end() = "}"
@@ -40,8 +45,15 @@ peg::parser!{grammar grammar() for str {
     rule text() -> (LinkPart, &'input str)
       = text:$((!link_start() [_])+) { (LinkPart::Text, text) }
 
+    rule expansion_mode() -> ExpansionMode
+      = "repeat" { ExpansionMode::Repeat }
+      / "indent" { ExpansionMode::Indent }
+
+    rule mode() -> ExpansionMode
+      = _ arg_separator() _ mode:expansion_mode() _? &link_end() { mode }
+
     rule link() -> (LinkPart, &'input str)
-      = link_start() _? name:$(name()) _? link_end() { (LinkPart::Link, name) }
+      = link_start() _? name:$(name()) mode:mode()? _? link_end() { (LinkPart::Link(mode), name) }
 
     pub rule link_line() -> LinkedLine<'input>
       = parts:((text() / link()) *) {
@@ -58,6 +70,7 @@ peg::parser!{grammar grammar() for str {
               parts: parts,
               slices: slices,
               text: "",
+              line_number: 0,
           }
         }
     }
@@ -128,6 +141,7 @@ pub struct LinkedLine<'a> {
     parts: Vec<LinkPart>,
     slices: Vec<&'a str>,
     text: &'a str,
+    line_number: usize,
 }
 
 impl<'a> LinkedLine<'a> {
@@ -152,26 +166,47 @@ impl<'a> LinkedLine<'a> {
         }
     }
 
+    pub fn segments<'b>(&'b self) -> impl Iterator<Item = LineSegment<'a>> + 'b {
+        self.parts.iter().zip(self.slices.iter()).map(|(part, &slice)| {
+            match part {
+                LinkPart::Text => LineSegment::Text(slice),
+                LinkPart::Link(mode) => LineSegment::Link { name: slice, mode: *mode },
+            }
+        })
+    }
+
     pub fn get_text<'b>(&'b self) -> &'b str {
         self.text
     }
+
+    /// The line of the .lit file that this line was read from
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
 }
 
 #[derive(Clone)]
 pub enum LinkPart {
-    Link,
+    /// A link, with the expansion mode it asked for, if any
+    Link(Option<ExpansionMode>),
     Text,
 }
 
 impl LinkPart {
     fn is_link(&self) -> bool {
         match self {
-            &LinkPart::Link => true,
+            &LinkPart::Link(_) => true,
             _ => false
         }
     }
 }
 
+/// A piece of a line, in the order it appears in the line
+pub enum LineSegment<'a> {
+    Text(&'a str),
+    Link { name: &'a str, mode: Option<ExpansionMode> },
+}
+
 type LinkInLine<'a, 'b> = (&'b [&'a str], &'a str, &'b [&'a str]);
 
 pub struct SplitLinks<'a : 'b, 'b> {
@@ -181,7 +216,7 @@ pub struct SplitLinks<'a : 'b, 'b> {
     slices: &'b [&'a str],
 }
 
-impl<'a, 'b : 'a> Iterator for SplitLinks<'a, 'b> {
+impl<'a : 'b, 'b> Iterator for SplitLinks<'a, 'b> {
     type Item = LinkInLine<'a, 'b>;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -216,21 +251,23 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     InfiniteCodeLoop,
     BadLinkName,
+    BadExpansionMode,
+    BadLink(usize),
 }
 
 fn link_lit_file<'a>(lit_file: &'a LitFile) -> Result<LinkedFile<'a>> {
     let mut link_map = LinkMap::new();
 
     let linked_sections : Vec<LinkedSection<'a>> = lit_file.sections.iter().map(|section| {
-        LinkedSection {
+        Ok(LinkedSection {
             id: section.id,
             depth: section.depth,
             name: section.name.as_str(),
             blocks: section.blocks.iter().map(|block| {
                 link_block(block, &mut link_map)
-            }).collect()
-        }
-    }).collect();
+            }).collect::<Result<_>>()?,
+        })
+    }).collect::<Result<_>>()?;
 
     let all_links : Vec<&'a str> = linked_sections.iter().flat_map(|section| {
         section.blocks.iter().flat_map(|block| {
@@ -242,6 +279,10 @@ fn link_lit_file<'a>(lit_file: &'a LitFile) -> Result<LinkedFile<'a>> {
 
     for link in all_links {
         if !(link_map.contains_key(link)) {
+            if let Some((name, mode)) = split_mode(link).filter(|(name, _)| link_map.contains_key(name)) {
+                error!("Found a link to \"{}\" with the expansion mode \"{}\", but the only modes are \"repeat\" and \"indent\"", name, mode);
+                Err(Error::BadExpansionMode)?;
+            }
             error!("Found a link to \"{}\", but that block doesn't exist", link);
             Err(Error::BadLinkName)?;
         }
@@ -292,10 +333,23 @@ fn link_lit_file<'a>(lit_file: &'a LitFile) -> Result<LinkedFile<'a>> {
     })
 }
 
-fn link_block<'a>(block: &'a Block, link_map : &mut LinkMap<'a>) -> LinkedBlock<'a> {
-    match block {
-        &Block::Code { ref name, modifiers, ref lines } => {
-            let linked_lines = link_lines(lines);
+// A link like `@{name --- mode}` whose mode isn't known is read as a link to a block named
+// `name --- mode`, so this splits it back up
+fn split_mode(link: &str) -> Option<(&str, &str)> {
+    let separator = link.rfind("---")?;
+    let (name, mode) = (&link[..separator], &link[separator + 3..]);
+
+    if name.ends_with(char::is_whitespace) && mode.starts_with(char::is_whitespace) {
+        Some((name.trim_end(), mode.trim_start()))
+    } else {
+        None
+    }
+}
+
+fn link_block<'a>(block: &'a Block, link_map : &mut LinkMap<'a>) -> Result<LinkedBlock<'a>> {
+    Ok(match block {
+        &Block::Code { ref name, modifiers, first_line, ref lines } => {
+            let linked_lines = link_lines(first_line, lines)?;
             let mut linked_to : Vec<&'a str> = linked_lines.iter()
                 .flat_map(|line| line.get_links())
                 .collect();
@@ -315,19 +369,24 @@ fn link_block<'a>(block: &'a Block, link_map : &mut LinkMap<'a>) -> LinkedBlock<
                 lines: linked_lines,
             }
         },
-        &Block::Prose { ref lines } => {
+        &Block::Prose { first_line, ref lines } => {
             LinkedBlock::Prose {
-                lines: link_lines(lines)
+                lines: link_lines(first_line, lines)?
             }
         }
-    }
+    })
 }
 
-fn link_lines<'a>(lines: &'a [String]) -> Vec<LinkedLine<'a>> {
-    lines.iter().map(|line| {
-        let mut linked_line = grammar::link_line(line).unwrap();
+fn link_lines<'a>(first_line: usize, lines: &'a [String]) -> Result<Vec<LinkedLine<'a>>> {
+    lines.iter().enumerate().map(|(offset, line)| {
+        let line_number = first_line + offset;
+        let mut linked_line = grammar::link_line(line).map_err(|err| {
+            error!("Couldn't read the links on line {}, at column {}: \"{}\"", line_number, err.location.column, line);
+            Error::BadLink(line_number)
+        })?;
         linked_line.text = line;
-        linked_line
+        linked_line.line_number = line_number;
+        Ok(linked_line)
     }).collect()
 }
 
@@ -29,13 +29,33 @@ pub struct CanonicalCodeBlock<'a> {
     print_header: bool,
     print_to_tangle: bool,
     print_to_weave: bool,
+    doc_comment: bool,
     form: CCBForm,
     contents: Vec<LinkedLine<'a>>,
+    fragments: Vec<Fragment<'a>>,
     pub first_defined_in: usize,
     appended_to_in: Vec<usize>,
     redefined_in: Vec<usize>,
 }
 
+/// A piece of a block, as it was written in one place in the .lit file
+pub struct Fragment<'a> {
+    pub section: usize,
+    pub section_name: Option<&'a str>,
+    pub kind: FragmentKind,
+    /// The number of lines that the piece adds to the block
+    pub length: usize,
+    /// The prose just before the piece, if there is any
+    pub prose: Vec<LinkedLine<'a>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FragmentKind {
+    Definition,
+    Append,
+    Redefinition,
+}
+
 pub enum CCBForm {
     File,
     Block,
@@ -47,8 +67,10 @@ impl<'a> CanonicalCodeBlock<'a> {
             print_header: true,
             print_to_tangle: true,
             print_to_weave: true,
+            doc_comment: false,
             form: form,
             contents: Vec::new(),
+            fragments: Vec::new(),
             first_defined_in: in_section,
             appended_to_in: Vec::new(),
             redefined_in: Vec::new(),
@@ -66,33 +88,66 @@ impl<'a> CanonicalCodeBlock<'a> {
         self.print_to_tangle
     }
 
+    /// The sections that the block is appended to in
+    pub fn appended_to_in(&self) -> &[usize] {
+        &self.appended_to_in[..]
+    }
+
+    /// The sections that the block is redefined in
+    pub fn redefined_in(&self) -> &[usize] {
+        &self.redefined_in[..]
+    }
+
+    pub fn print_to_weave(&self) -> bool {
+        self.print_to_weave
+    }
+
     pub fn print_header(&self) -> bool {
         self.print_header
     }
 
+    pub fn doc_comment(&self) -> bool {
+        self.doc_comment
+    }
+
     pub fn contents(&self) -> &[LinkedLine<'a>] {
         &self.contents[..]
     }
+
+    /// The pieces that the contents are made of, in order
+    pub fn fragments(&self) -> &[Fragment<'a>] {
+        &self.fragments[..]
+    }
     
-    fn append_lines(&mut self, lines: &[LinkedLine<'a>]) {
+    fn append_lines(&mut self, lines: &[LinkedLine<'a>], section: &LinkedSection<'a>, kind: FragmentKind, prose: &[LinkedLine<'a>]) {
         self.contents.extend_from_slice(lines);
+        self.fragments.push(Fragment {
+            section: section.id,
+            section_name: section.name,
+            kind,
+            length: lines.len(),
+            prose: prose.to_vec(),
+        });
     }
 
-    fn replace_lines(&mut self, lines: &[LinkedLine<'a>]) {
+    fn replace_lines(&mut self, lines: &[LinkedLine<'a>], section: &LinkedSection<'a>, kind: FragmentKind, prose: &[LinkedLine<'a>]) {
         self.contents.clear();
-        self.append_lines(lines);
+        self.fragments.clear();
+        self.append_lines(lines, section, kind, prose);
     }
 
     fn set_modifiers(&mut self, modifiers: BlockModifier) {
         self.print_header = !modifiers.contains(BlockModifier::NOHEADER);
         self.print_to_tangle = !modifiers.contains(BlockModifier::NOTANGLE);
         self.print_to_weave = !modifiers.contains(BlockModifier::NOWEAVE);
+        self.doc_comment = modifiers.contains(BlockModifier::DOCCOMMENT);
     }
     
     fn add_modifiers(&mut self, modifiers: BlockModifier) {
         self.print_header &= !modifiers.contains(BlockModifier::NOHEADER);
         self.print_to_tangle &= !modifiers.contains(BlockModifier::NOTANGLE);
         self.print_to_weave &= !modifiers.contains(BlockModifier::NOWEAVE);
+        self.doc_comment |= modifiers.contains(BlockModifier::DOCCOMMENT);
     }
 
     fn mark_redefined(&mut self, in_section: usize) {
@@ -110,22 +165,26 @@ pub fn canonicalise_code_blocks<'a>(sections: &[LinkedSection<'a>]) -> BlockMap<
     let mut block_map : BlockMap<'a> = HashMap::new();
     
     for section in sections {
+        // Only prose directly before a code block belongs to it
+        let mut preceding_prose : &[LinkedLine<'a>] = &[];
+
         for block in section.blocks.iter() {
             match block {
                 LinkedBlock::Code { ref name, modifiers, ref lines } => {
+                    let prose = std::mem::take(&mut preceding_prose);
                     let name = name.as_str();
                     if block_map.contains_key(name) {
                         let canonical = block_map.get_mut(name).unwrap();
 
                         if modifiers.contains(BlockModifier::REDEF) {
-                            canonical.replace_lines(lines);
+                            canonical.replace_lines(lines, section, FragmentKind::Redefinition, prose);
                             canonical.set_modifiers(*modifiers);
                             canonical.mark_redefined(section.id);
                         } else {
                             if modifiers.contains(BlockModifier::APPEND) {
                                 canonical.add_modifiers(*modifiers);
                             }
-                            canonical.append_lines(lines);
+                            canonical.append_lines(lines, section, FragmentKind::Append, prose);
                             canonical.mark_appended(section.id);
                         }
                     } else {
@@ -136,13 +195,15 @@ pub fn canonicalise_code_blocks<'a>(sections: &[LinkedSection<'a>]) -> BlockMap<
                         };
 
                         let mut canonical = CanonicalCodeBlock::from_form(form, section.id);
-                        canonical.replace_lines(lines);
+                        canonical.replace_lines(lines, section, FragmentKind::Definition, prose);
                         canonical.set_modifiers(*modifiers);
 
                         block_map.insert(name, canonical);
                     }
                 },
-                _ => {}
+                LinkedBlock::Prose { ref lines } => {
+                    preceding_prose = lines;
+                },
             }
         }
     }
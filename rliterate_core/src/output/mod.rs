@@ -21,13 +21,17 @@
 
 pub mod css;
 mod canon;
+mod scratch;
+pub use self::scratch::ScratchDir;
 pub mod tangle;
 pub mod weave;
 
 use super::link;
 
 use subprocess::{PopenError};
-use std::io;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -39,8 +43,20 @@ pub enum Error {
     FailedCommand(u32),
     TerminatedCommand(u8),
     NoCompilerCommand,
-    FailedCompiler(u32),
-    TerminatedCompiler(u8),
+    FailedCompiler(u32, CompilerOutput),
+    TerminatedCompiler(u8, CompilerOutput),
+    UntangleConflicts(usize),
+    UnsafeOutputPath(PathBuf),
+    HandEdited(PathBuf),
+    MissingRegion(PathBuf, String),
+    DuplicateRegion(PathBuf, String),
+}
+
+/// What a compiler printed, with its locations pointing at the .lit file where possible
+#[derive(Debug)]
+pub struct CompilerOutput {
+    pub stdout: String,
+    pub stderr: String,
 }
 
 impl From<io::Error> for Error {
@@ -64,6 +80,8 @@ impl Globals {
 
     pub fn process<'a>(&self, link_state: link::LinkState<'a>) -> Result<()> {
         trace!("Started outputting files...");
+        let books = weave::Books::build(&link_state.file_map);
+
         for (path, linked_file) in link_state.file_map.iter() {
             let canonical_code_blocks = canon::canonicalise_code_blocks(&linked_file.sections[..]);
 
@@ -73,16 +91,43 @@ impl Globals {
                     let line_numbers =  linked_file.line_number_format.as_ref().or(global_settings.line_numbers.as_ref());
                     let file_level_settings = tangle::Settings {
                       global_settings,
+                      lit_file: path,
                       relative_directory: &linked_file.relative_directory,
                       line_numbers,
                       comment_formatter: linked_file.comment_type.as_ref(),
+                      expansion_mode: linked_file.expansion_mode,
                       compiler: &linked_file.compiler,
+                      line_ending: linked_file.line_ending
+                          .or(global_settings.line_ending)
+                          .unwrap_or(linked_file.source_line_ending),
+                      strip_trailing_whitespace: linked_file.strip_trailing_whitespace || global_settings.strip_trailing_whitespace,
+                      final_newline: linked_file.final_newline || global_settings.final_newline,
+                      banner: linked_file.banner.as_deref().or(global_settings.banner.as_deref()),
+                      block_markers: linked_file.block_markers || global_settings.block_markers,
+                      doc_comment_formatter: linked_file.doc_comment_type.as_ref().or(linked_file.comment_type.as_ref()),
+                      prose_comments: linked_file.prose_comments,
+                      formatter: linked_file.formatter.as_deref().or(global_settings.formatter.as_deref()),
                     };
-                    tangle::tangle_blocks(file_level_settings, &canonical_code_blocks)?;
+                    if global_settings.untangle {
+                        tangle::untangle_blocks(file_level_settings, &canonical_code_blocks)?;
+                    } else {
+                        tangle::tangle_blocks(file_level_settings, &canonical_code_blocks)?;
+                    }
                 }
 
                 if let Some(ref global_settings) = self.weave {
-                    weave::weave_file_with_blocks(global_settings, path, linked_file, &canonical_code_blocks)?;
+                    // Books are woven whole when they go on a single page, or into an mdBook
+                    let whole_books = global_settings.single_page || matches!(global_settings.weave_type, weave::Type::MdBook);
+
+                    if !whole_books {
+                        weave::weave_file_with_blocks(global_settings, path, linked_file, &canonical_code_blocks, &books)?;
+                    } else if books.is_chapter(path) {
+                        trace!("\"{}\" is woven along with its book", path.to_string_lossy());
+                    } else if books.is_book(path) {
+                        weave::weave_book(global_settings, path, &link_state.file_map)?;
+                    } else {
+                        weave::weave_file_with_blocks(global_settings, path, linked_file, &canonical_code_blocks, &books)?;
+                    }
                 }
                 trace!("Finished generating output for \"{}\"", path.to_string_lossy());
             }
@@ -93,3 +138,159 @@ impl Globals {
     }
 
 }
+
+/// Write some output to a file, unless the file already holds exactly that output. Files that do
+/// change are written to a temporary file first and then renamed into place, so that a crash
+/// can't leave a half-written file behind, and given the permissions of the file they replace.
+/// If the file is a symlink, the file it points to is written instead, and the link is kept.
+/// Returns whether the file was written.
+pub fn write_if_changed(path: &Path, contents: &[u8]) -> io::Result<bool> {
+    let path = &resolve_symlink(path)?;
+
+    let permissions = match fs::read(path) {
+        Ok(ref existing) if existing.as_slice() == contents => {
+            debug!("\"{}\" is unchanged, so it won't be rewritten", path.to_string_lossy());
+            return Ok(false);
+        },
+        Ok(_) => Some(fs::metadata(path)?.permissions()),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+
+    let (mut temp_file, temp_path) = create_temp_file(path)?;
+
+    let result = temp_file.write_all(contents)
+        .and_then(|_| match permissions {
+            Some(permissions) => temp_file.set_permissions(permissions),
+            None => Ok(()),
+        })
+        .and_then(|_| temp_file.sync_all())
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result.map(|_| true)
+}
+
+/// How many symlinks to follow before deciding that they go round in a loop
+const MAX_SYMLINKS : usize = 40;
+
+/// Follow a path through any symlinks to the file it ends up at - which may not exist yet
+fn resolve_symlink(path: &Path) -> io::Result<PathBuf> {
+    let mut resolved = path.to_path_buf();
+
+    for _ in 0..MAX_SYMLINKS {
+        match fs::symlink_metadata(&resolved) {
+            Ok(ref metadata) if metadata.file_type().is_symlink() => {
+                let target = fs::read_link(&resolved)?;
+                trace!("\"{}\" is a symlink to \"{}\"", resolved.to_string_lossy(), target.to_string_lossy());
+                // A relative target is relative to the directory the link is in
+                resolved = match resolved.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                };
+            },
+            _ => return Ok(resolved),
+        }
+    }
+
+    Err(io::Error::other(format!("too many levels of symlinks at \"{}\"", path.to_string_lossy())))
+}
+
+/// Create a temporary file next to a file, with a name that nothing else is using - a file left
+/// behind by an earlier run that crashed is skipped over
+fn create_temp_file(path: &Path) -> io::Result<(fs::File, PathBuf)> {
+    for _ in 0..scratch::MAX_ATTEMPTS {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(path.file_name().unwrap_or_default());
+        temp_name.push(format!(".{:016x}.tmp", scratch::random_suffix()));
+        let temp_path = path.with_file_name(temp_name);
+
+        match fs::OpenOptions::new().create_new(true).write(true).open(&temp_path) {
+            Ok(temp_file) => return Ok((temp_file, temp_path)),
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                trace!("The temporary file \"{}\" already exists", temp_path.to_string_lossy());
+            },
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("couldn't find an unused name for a temporary file next to \"{}\"", path.to_string_lossy())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ScratchDir, write_if_changed};
+
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn new_files_are_written() {
+        let dir = ScratchDir::new("test").unwrap();
+        let path = dir.path().join("main.rs");
+
+        assert!(write_if_changed(&path, b"fn main() {}\n").unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() {}\n");
+    }
+
+    #[test]
+    fn unchanged_files_are_not_rewritten() {
+        let dir = ScratchDir::new("test").unwrap();
+        let path = dir.path().join("main.rs");
+        fs::write(&path, "fn main() {}\n").unwrap();
+        // Far enough in the past that a rewrite couldn't keep the same time
+        let modified = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+
+        assert!(!write_if_changed(&path, b"fn main() {}\n").unwrap());
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_are_kept() {
+        use std::os::unix::fs::{PermissionsExt};
+
+        let dir = ScratchDir::new("test").unwrap();
+        let path = dir.path().join("run.sh");
+        fs::write(&path, "echo hi\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+
+        assert!(write_if_changed(&path, b"echo bye\n").unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo bye\n");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o751);
+    }
+
+    #[test]
+    fn temporary_files_left_behind_are_skipped() {
+        let dir = ScratchDir::new("test").unwrap();
+        let path = dir.path().join("main.rs");
+        // An earlier version named temporary files after the process
+        let stale = dir.path().join(format!(".main.rs.{}.tmp", std::process::id()));
+        fs::write(&stale, "stale").unwrap();
+
+        assert!(write_if_changed(&path, b"fn main() {}\n").unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() {}\n");
+        assert_eq!(fs::read_to_string(&stale).unwrap(), "stale");
+        // Nothing else is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_written_through() {
+        use std::os::unix::fs::{symlink};
+
+        let dir = ScratchDir::new("test").unwrap();
+        fs::create_dir(dir.path().join("real")).unwrap();
+        fs::write(dir.path().join("real/main.rs"), "fn main() {}\n").unwrap();
+        symlink("real/main.rs", dir.path().join("main.rs")).unwrap();
+
+        assert!(write_if_changed(&dir.path().join("main.rs"), b"fn main() { run(); }\n").unwrap());
+        assert!(fs::symlink_metadata(dir.path().join("main.rs")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(dir.path().join("real/main.rs")).unwrap(), "fn main() { run(); }\n");
+    }
+}
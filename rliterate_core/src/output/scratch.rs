@@ -0,0 +1,100 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Scratch directories, for output that shouldn't end up in the user's workspace.
+
+use std::collections::hash_map::{RandomState};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static SCRATCH_COUNT : AtomicUsize = AtomicUsize::new(0);
+
+/// How many names to try before giving up on finding one that isn't taken
+pub const MAX_ATTEMPTS : usize = 100;
+
+/// A temporary directory, which is deleted along with everything in it when dropped
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    /// Create a fresh directory under the system temporary directory. Its name can't be guessed
+    /// ahead of time, and anything already at that path - including a symlink - is refused
+    /// rather than reused.
+    pub fn new(name: &str) -> io::Result<Self> {
+        for _ in 0..MAX_ATTEMPTS {
+            let path = std::env::temp_dir().join(format!("rliterate-{}-{:016x}", name, random_suffix()));
+
+            match create_private_dir(&path) {
+                Ok(()) => {
+                    debug!("Created the scratch directory \"{}\"", path.to_string_lossy());
+                    return Ok(ScratchDir { path });
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    trace!("The scratch directory \"{}\" already exists", path.to_string_lossy());
+                },
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::AlreadyExists, "couldn't find an unused name for a scratch directory"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.path) {
+            warn!("Failed to clean up the scratch directory \"{}\": {}", self.path.to_string_lossy(), err);
+        }
+    }
+}
+
+/// A suffix which another process can't predict - the standard library seeds every
+/// `RandomState` randomly, so hashing with one gives a random number
+pub fn random_suffix() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.write_usize(SCRATCH_COUNT.fetch_add(1, Ordering::SeqCst));
+    if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.finish()
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt};
+
+    fs::DirBuilder::new().mode(0o700).create(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    fs::create_dir(path)
+}
@@ -0,0 +1,210 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Banners, which mark the top of a tangled file as generated, and protect it from being
+//! overwritten after it has been edited by hand.
+//!
+//! A banner is two comments, in the file's `@comment_type`:
+//!
+//! ```text
+//! // Generated from main.lit, do not edit
+//! // lit-hash: 1f2e3d4c5b6a7988
+//! ```
+//!
+//! In a script which starts with a `#!` line, the banner goes just after that line instead, so
+//! that the script still runs.
+//!
+//! The hash is a 64-bit FNV-1a hash of everything in the file apart from the banner. If the rest
+//! of the file no longer matches its hash, the file has been edited since it was tangled.
+
+use super::{Settings, TangledLine};
+use parser::{FormatFn};
+use output;
+
+use std::borrow::{Cow};
+use std::fs;
+use std::io;
+use std::path::{Path};
+
+const HASH_MARKER : &str = "lit-hash: ";
+const SHEBANG : &str = "#!";
+// The hash should only be looked for in the banner
+const BANNER_SEARCH_LINES : usize = 5;
+
+/// Put a banner at the top of some tangled lines
+pub fn add_banner<'a>(settings: &Settings, banner: &str, comment_formatter: &FormatFn<String>, lines: &mut Vec<TangledLine<'a>>) {
+    let mut body = String::new();
+    for line in lines.iter() {
+        body.push_str(&line.text);
+        body.push_str(settings.line_ending.as_str());
+    }
+
+    let lit_file_name = settings.lit_file.file_name().unwrap_or_default().to_string_lossy();
+    let banner_lines = vec![
+        comment_formatter(banner.replace("%f", &lit_file_name)),
+        comment_formatter(format!("{}{:016x}", HASH_MARKER, hash(body.as_bytes()))),
+    ];
+
+    let position = match lines.first() {
+        Some(first_line) if first_line.text.starts_with(SHEBANG) => 1,
+        _ => 0,
+    };
+
+    lines.splice(position..position, banner_lines.into_iter().map(|text| TangledLine {
+        text,
+        origin: None,
+    }));
+}
+
+/// Make sure that a file can be overwritten with some new contents, because either it hasn't been
+/// edited by hand since it was last tangled, or the edits are already in the new contents
+pub fn check_for_hand_edits(path: &Path, new_contents: &[u8]) -> output::Result<()> {
+    let existing = match fs::read(path) {
+        Ok(existing) => existing,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let (recorded_hash, body) = match find_hash(&existing) {
+        Some(found) => found,
+        None => return Ok(()),
+    };
+
+    if hash(&body) == recorded_hash || find_hash(new_contents).is_some_and(|(_, new_body)| new_body == body) {
+        Ok(())
+    } else {
+        error!("\"{}\" has been edited by hand since it was tangled - move the edits into the .lit file (--untangle can do this), or pass --force to overwrite them",
+               path.to_string_lossy());
+        Err(output::Error::HandEdited(path.to_path_buf()))
+    }
+}
+
+/// Find the hash recorded in a file's banner, along with the rest of the file around it
+fn find_hash<'c>(contents: &'c [u8]) -> Option<(u64, Cow<'c, [u8]>)> {
+    let mut position = 0;
+
+    for _ in 0..BANNER_SEARCH_LINES {
+        let line_end = contents[position..].iter().position(|&byte| byte == b'\n')? + position;
+        let line = String::from_utf8_lossy(&contents[position..line_end]);
+
+        if let Some(marker) = line.find(HASH_MARKER) {
+            let digits = line[marker + HASH_MARKER.len()..].get(..16)?;
+            let hash = u64::from_str_radix(digits, 16).ok()?;
+            return Some((hash, without_banner(contents, line_end + 1)));
+        }
+
+        position = line_end + 1;
+    }
+
+    None
+}
+
+/// The contents of a file whose banner ends at `banner_end`, keeping a `#!` line from above it
+fn without_banner<'c>(contents: &'c [u8], banner_end: usize) -> Cow<'c, [u8]> {
+    if !contents.starts_with(SHEBANG.as_bytes()) {
+        return Cow::Borrowed(&contents[banner_end..]);
+    }
+
+    // The `#!` line is the first line, and the banner starts on the next one
+    let shebang_end = contents.iter().position(|&byte| byte == b'\n').map_or(contents.len(), |end| end + 1);
+    let mut body = contents[..shebang_end].to_vec();
+    body.extend_from_slice(&contents[banner_end..]);
+    Cow::Owned(body)
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS : u64 = 0xcbf29ce484222325;
+    const PRIME : u64 = 0x100000001b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_for_hand_edits, hash};
+    use output;
+    use output::{ScratchDir};
+
+    use std::fs;
+
+    /// A file with a banner, and the hash of its contents without that banner
+    fn with_banner(shebang: &str, body: &str) -> String {
+        let hash = hash(format!("{}{}", shebang, body).as_bytes());
+        format!("{}// Generated from main.lit, do not edit\n// lit-hash: {:016x}\n{}", shebang, hash, body)
+    }
+
+    fn check(existing: &str, new_contents: &str) -> output::Result<()> {
+        let out_dir = ScratchDir::new("test").unwrap();
+        let path = out_dir.path().join("main.rs");
+        fs::write(&path, existing).unwrap();
+
+        check_for_hand_edits(&path, new_contents.as_bytes())
+    }
+
+    fn is_hand_edited(result: output::Result<()>) -> bool {
+        match result {
+            Err(output::Error::HandEdited(path)) => path.ends_with("main.rs"),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn an_unchanged_file_can_be_overwritten() {
+        let existing = with_banner("", "fn main() {}\n");
+
+        assert!(check(&existing, &with_banner("", "fn main() { run(); }\n")).is_ok());
+    }
+
+    #[test]
+    fn a_hand_edited_file_is_kept() {
+        let existing = with_banner("", "fn main() {}\n").replace("{}", "{ edited(); }");
+
+        assert!(is_hand_edited(check(&existing, &with_banner("", "fn main() { run(); }\n"))));
+    }
+
+    #[test]
+    fn hand_edits_already_in_the_new_contents_can_be_overwritten() {
+        let existing = with_banner("", "fn main() {}\n").replace("{}", "{ edited(); }");
+
+        assert!(check(&existing, &with_banner("", "fn main() { edited(); }\n")).is_ok());
+    }
+
+    #[test]
+    fn the_shebang_line_is_part_of_the_hash() {
+        let existing = with_banner("#!/bin/sh\n", "echo hi\n");
+        assert!(check(&existing, &with_banner("#!/bin/sh\n", "echo bye\n")).is_ok());
+
+        let edited = existing.replace("#!/bin/sh", "#!/bin/bash");
+        assert!(is_hand_edited(check(&edited, &with_banner("#!/bin/sh\n", "echo bye\n"))));
+    }
+
+    #[test]
+    fn a_file_without_a_banner_can_be_overwritten() {
+        assert!(check("fn main() {}\n", &with_banner("", "fn main() { run(); }\n")).is_ok());
+    }
+
+    #[test]
+    fn a_missing_file_can_be_written() {
+        let out_dir = ScratchDir::new("test").unwrap();
+
+        assert!(check_for_hand_edits(&out_dir.path().join("main.rs"), b"fn main() {}\n").is_ok());
+    }
+}
@@ -0,0 +1,296 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Compiling tangled code, and pointing the compiler's messages back at the .lit file.
+
+use super::{Settings, TangledLine, output_path, print_lines, region, tangle_file};
+use parser::{CompilerSettings, ErrorFormat};
+use output;
+use output::canon::{CanonicalCodeBlock, BlockMap};
+
+use subprocess;
+
+use std::fs;
+use std::path::{Path};
+
+/// A tangled file, as the compiler will see it
+pub struct CompiledFile<'t, 'a: 't> {
+    pub path: &'t Path,
+    pub lines: &'t [TangledLine<'a>],
+}
+
+/// Tangle the file blocks of a .lit file into a scratch directory, and run the compiler command
+/// there once for all of them
+pub fn compile_blocks<'a>(settings: &Settings,
+                          file_blocks: &[(&&'a str, &CanonicalCodeBlock<'a>)],
+                          blocks: &BlockMap<'a>) -> output::Result<()> {
+    let compiler_settings = settings.compiler.as_ref().ok_or(output::Error::NoCompilerCommand)?;
+    let scratch = output::ScratchDir::new("compile")?;
+
+    let mut tangled_files = Vec::new();
+    for (name, block) in file_blocks {
+        if let (_, Some(_)) = region::split_region(name) {
+            warn!("\"{}\" is tangled into a region of a hand-written file, so it won't be compiled", name);
+            continue;
+        }
+
+        // The scratch directory is thrown away afterwards, so nothing may be written outside of it
+        let path = output_path(scratch.path(), false, &settings.relative_directory.join(name))?;
+        info!("Found a file block \"{}\", tangling to \"{}\" to compile it", name, path.to_string_lossy());
+
+        let lines = tangle_file(settings, name, block, blocks);
+
+        if let Some(parent) = path.parent() {
+            fs::DirBuilder::new().recursive(true).create(parent)?;
+        }
+
+        let mut contents = Vec::new();
+        print_lines(&mut contents, &lines, settings.line_ending)?;
+        fs::write(&path, contents)?;
+
+        tangled_files.push((path, lines));
+    }
+
+    let files : Vec<CompiledFile> = tangled_files.iter()
+        .map(|(path, lines)| CompiledFile { path, lines })
+        .collect();
+
+    compile_files(compiler_settings, settings.lit_file, scratch.path(), &files)
+}
+
+/// Run the compiler command from the directory that the files were tangled to
+pub fn compile_files(compiler_settings: &CompilerSettings, lit_file: &Path, directory: &Path, files: &[CompiledFile]) -> output::Result<()> {
+    let command = substitute_command(&compiler_settings.command, directory, files);
+
+    trace!("Compiling the code from \"{}\" with \"{}\"...", lit_file.to_string_lossy(), command);
+    let compiler_result = subprocess::Exec::shell(&command)
+        .cwd(directory)
+        .stdout(subprocess::Redirection::Pipe)
+        .stderr(subprocess::Redirection::Pipe)
+        .capture()?;
+
+    let compiler_output = match compiler_settings.error_format {
+        Some(ref error_format) => output::CompilerOutput {
+            stdout: remap_output(&compiler_result.stdout_str(), error_format, lit_file, files),
+            stderr: remap_output(&compiler_result.stderr_str(), error_format, lit_file, files),
+        },
+        None => output::CompilerOutput {
+            stdout: compiler_result.stdout_str(),
+            stderr: compiler_result.stderr_str(),
+        },
+    };
+
+    trace!("Finished compiling the code from \"{}\"", lit_file.to_string_lossy());
+    match compiler_result.exit_status {
+        subprocess::ExitStatus::Exited(0) => {
+            // The output of a failed compiler goes back with the error, but warnings from one which
+            // succeeded would be lost otherwise
+            if !compiler_output.stdout.is_empty() || !compiler_output.stderr.is_empty() {
+                warn!("The compiler succeeded on the code from \"{}\", but said:\n{}{}",
+                      lit_file.to_string_lossy(), compiler_output.stdout, compiler_output.stderr);
+            }
+            Ok(())
+        },
+        subprocess::ExitStatus::Exited(code) => Err(output::Error::FailedCompiler(code, compiler_output)),
+        subprocess::ExitStatus::Signaled(signal) => Err(output::Error::TerminatedCompiler(signal, compiler_output)),
+        _ => unreachable!(),
+    }
+}
+
+/// Fill in the placeholders in a compiler command:
+///
+///  * `%f` - the tangled files, relative to the directory that the command is run from
+///  * `%d` - the directory that the files were tangled to
+///  * `%%` - a literal `%`
+///
+/// Any other `%` is left alone.
+fn substitute_command(command: &str, directory: &Path, files: &[CompiledFile]) -> String {
+    let file_list = files.iter()
+        .map(|file| shell_quote(&file.path.strip_prefix(directory).unwrap_or(file.path).to_string_lossy()))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let mut substituted = String::with_capacity(command.len());
+    let mut characters = command.chars().peekable();
+
+    while let Some(character) = characters.next() {
+        if character != '%' {
+            substituted.push(character);
+            continue;
+        }
+
+        match characters.peek() {
+            Some('f') => substituted.push_str(&file_list),
+            Some('d') => substituted.push_str(&shell_quote(&directory.to_string_lossy())),
+            Some('%') => substituted.push('%'),
+            _ => {
+                substituted.push('%');
+                continue;
+            },
+        }
+        characters.next();
+    }
+
+    substituted
+}
+
+fn shell_quote(text: &str) -> String {
+    let is_plain = !text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric() || "_-./+".contains(c));
+
+    if is_plain {
+        text.to_owned()
+    } else {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+/// Rewrite the locations in some compiler output, so that they point at the .lit file
+pub fn remap_output(output: &str, error_format: &ErrorFormat, lit_file: &Path, files: &[CompiledFile]) -> String {
+    output.split_inclusive('\n')
+        .map(|line| {
+            let content = line.trim_end_matches(&['\n', '\r'][..]);
+            match remap_line(content, error_format, lit_file, files) {
+                Some(remapped) => format!("{}{}", remapped, &line[content.len()..]),
+                None => line.to_owned(),
+            }
+        })
+        .collect()
+}
+
+fn remap_line(line: &str, error_format: &ErrorFormat, lit_file: &Path, files: &[CompiledFile]) -> Option<String> {
+    let message = error_format.match_line(line)?;
+
+    let reported_file = Path::new(line[message.file.clone()].trim_start_matches("./"));
+    let file = files.iter().find(|file| {
+        file.path == reported_file || file.path.ends_with(reported_file) || reported_file.ends_with(file.path)
+    })?;
+
+    let line_number : usize = line[message.line.clone()].parse().ok()?;
+    let origin = file.lines.get(line_number.checked_sub(1)?)?.origin.as_ref()?;
+    let lit_line = origin.lit_line?;
+
+    let mut replacements = vec![
+        (message.file, lit_file.to_string_lossy().into_owned()),
+        (message.line, lit_line.to_string()),
+    ];
+
+    if let Some(column_range) = message.column {
+        let column : usize = line[column_range.clone()].parse().ok()?;
+        // Columns that point into text from an enclosing block get clamped to the start of the line
+        let lit_column = if column > origin.offset { column - origin.offset } else { 1 };
+        replacements.push((column_range, lit_column.to_string()));
+    }
+
+    replacements.sort_by_key(|(range, _)| range.start);
+
+    let mut remapped = String::with_capacity(line.len());
+    let mut position = 0;
+    for (range, replacement) in replacements {
+        remapped.push_str(&line[position..range.start]);
+        remapped.push_str(&replacement);
+        position = range.end;
+    }
+    remapped.push_str(&line[position..]);
+
+    debug!("Remapped compiler output \"{}\" to \"{}\"", line, remapped);
+    Some(remapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompiledFile, remap_line, substitute_command};
+    use super::super::{Origin, TangledLine};
+    use parser::{ErrorFormat};
+
+    use std::path::{Path};
+
+    fn line(text: &str, lit_line: usize, offset: usize) -> TangledLine<'static> {
+        TangledLine {
+            text: text.to_owned(),
+            origin: Some(Origin {
+                lit_line: Some(lit_line),
+                offset,
+                block: "body",
+                expansion_chain: vec!["main.rs", "body"],
+            }),
+        }
+    }
+
+    #[test]
+    fn files_and_directories_are_substituted() {
+        let files = [
+            CompiledFile { path: Path::new("/tmp/out/main.rs"), lines: &[] },
+            CompiledFile { path: Path::new("/tmp/out/src/lib.rs"), lines: &[] },
+        ];
+
+        assert_eq!(substitute_command("rustc %f --out-dir %d", Path::new("/tmp/out"), &files),
+                   "rustc main.rs src/lib.rs --out-dir /tmp/out");
+    }
+
+    #[test]
+    fn percent_signs_can_be_escaped() {
+        assert_eq!(substitute_command("printf '100%%' %x %", Path::new("/tmp/out"), &[]),
+                   "printf '100%' %x %");
+    }
+
+    #[test]
+    fn paths_are_quoted_for_the_shell() {
+        let files = [
+            CompiledFile { path: Path::new("/tmp/my out/it's.rs"), lines: &[] },
+        ];
+
+        assert_eq!(substitute_command("cc %f -o %d/a", Path::new("/tmp/my out"), &files),
+                   "cc 'it'\\''s.rs' -o '/tmp/my out'/a");
+    }
+
+    #[test]
+    fn locations_are_remapped_to_the_lit_file() {
+        let error_format = ErrorFormat::parse("%f:%l:%c: %m").unwrap();
+        let lines = [line("fn main() {", 10, 0), line("    let x = y;", 12, 4)];
+        let files = [CompiledFile { path: Path::new("main.rs"), lines: &lines }];
+
+        assert_eq!(remap_line("./main.rs:2:13: cannot find value `y`", &error_format, Path::new("lit/main.lit"), &files),
+                   Some("lit/main.lit:12:9: cannot find value `y`".to_owned()));
+    }
+
+    #[test]
+    fn columns_before_the_lit_text_are_clamped() {
+        let error_format = ErrorFormat::parse("%f:%l:%c: %m").unwrap();
+        let lines = [line("fn main() {", 10, 0), line("    let x = y;", 12, 4)];
+        let files = [CompiledFile { path: Path::new("main.rs"), lines: &lines }];
+
+        assert_eq!(remap_line("main.rs:2:4: unexpected indentation", &error_format, Path::new("lit/main.lit"), &files),
+                   Some("lit/main.lit:12:1: unexpected indentation".to_owned()));
+        assert_eq!(remap_line("main.rs:2:5: unused variable", &error_format, Path::new("lit/main.lit"), &files),
+                   Some("lit/main.lit:12:1: unused variable".to_owned()));
+    }
+
+    #[test]
+    fn unknown_locations_are_left_alone() {
+        let error_format = ErrorFormat::parse("%f:%l:%c: %m").unwrap();
+        let lines = [line("fn main() {", 10, 0)];
+        let files = [CompiledFile { path: Path::new("main.rs"), lines: &lines }];
+
+        assert_eq!(remap_line("other.rs:1:1: error", &error_format, Path::new("lit/main.lit"), &files), None);
+        assert_eq!(remap_line("main.rs:5:1: error", &error_format, Path::new("lit/main.lit"), &files), None);
+        assert_eq!(remap_line("error: aborting", &error_format, Path::new("lit/main.lit"), &files), None);
+    }
+}
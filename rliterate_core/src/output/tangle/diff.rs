@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! A minimal line diff, used to relate a fresh tangle to some other version of the same output.
+
+use std::ops::Range;
+
+// Past this many cells, the LCS table gets too big to be worth building, and the changed region
+// is reported as a single hunk instead
+const MAX_TABLE_SIZE: usize = 4_000_000;
+
+/// A region where the old and new sequences differ
+#[derive(Debug, PartialEq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Compute the hunks that turn `old` into `new`, in order
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    if old_middle.is_empty() && new_middle.is_empty() {
+        return vec![];
+    }
+
+    if old_middle.len().saturating_mul(new_middle.len()) > MAX_TABLE_SIZE {
+        return vec![Hunk {
+            old: prefix..old.len() - suffix,
+            new: prefix..new.len() - suffix,
+        }];
+    }
+
+    let width = new_middle.len() + 1;
+    // lcs[i * width + j] is the length of the longest common subsequence of old_middle[i..] and
+    // new_middle[j..]
+    let mut lcs = vec![0_usize; (old_middle.len() + 1) * width];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lcs[i * width + j] = if old_middle[i] == new_middle[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk> = None;
+    let (mut i, mut j) = (0, 0);
+
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            hunks.extend(current.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let hunk = current.get_or_insert(Hunk {
+            old: prefix + i..prefix + i,
+            new: prefix + j..prefix + j,
+        });
+
+        if j == new_middle.len() || (i < old_middle.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+            i += 1;
+            hunk.old.end = prefix + i;
+        } else {
+            j += 1;
+            hunk.new.end = prefix + j;
+        }
+    }
+    hunks.extend(current);
+
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, Hunk};
+
+    use std::ops::Range;
+
+    fn hunk(old: Range<usize>, new: Range<usize>) -> Hunk {
+        Hunk { old, new }
+    }
+
+    #[test]
+    fn equal_lines_have_no_hunks() {
+        assert_eq!(diff(&["a", "b"], &["a", "b"]), vec![]);
+    }
+
+    #[test]
+    fn an_edit_replaces_lines() {
+        assert_eq!(diff(&["a", "b", "c"], &["a", "B", "c"]), vec![hunk(1..2, 1..2)]);
+    }
+
+    #[test]
+    fn an_insertion_replaces_nothing() {
+        assert_eq!(diff(&["a", "c"], &["a", "b", "c"]), vec![hunk(1..1, 1..2)]);
+    }
+
+    #[test]
+    fn a_deletion_inserts_nothing() {
+        assert_eq!(diff(&["a", "b", "c"], &["a", "c"]), vec![hunk(1..2, 1..1)]);
+    }
+
+    #[test]
+    fn separate_changes_are_separate_hunks() {
+        assert_eq!(diff(&["a", "b", "c", "d", "e"], &["a", "B", "c", "e", "f"]),
+                   vec![hunk(1..2, 1..2), hunk(3..4, 3..3), hunk(5..5, 4..5)]);
+    }
+
+    #[test]
+    fn too_many_changes_are_one_hunk() {
+        let with_ends = |middle: Vec<String>| {
+            let mut lines = vec!["first".to_owned()];
+            lines.extend(middle);
+            lines.push("last".to_owned());
+            lines
+        };
+
+        // The lines which differ are too many to compare one by one, so the line that they share
+        // in the middle isn't found
+        let old : Vec<String> = (0..2001).map(|line| format!("old {}", line)).collect();
+        let mut new : Vec<String> = (0..2001).map(|line| format!("new {}", line)).collect();
+        new[1000] = old[1000].clone();
+
+        let old = with_ends(old);
+        let new = with_ends(new);
+        assert_eq!(diff(&old, &new), vec![hunk(1..2002, 1..2002)]);
+
+        // Fewer changes are compared line by line
+        assert_eq!(diff(&old[..1500], &new[..1500]), vec![hunk(1..1001, 1..1001), hunk(1002..1500, 1002..1500)]);
+    }
+}
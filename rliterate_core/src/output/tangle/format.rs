@@ -0,0 +1,174 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Running a formatter, like `rustfmt`, over tangled code.
+//!
+//! The formatter gets the tangled text on stdin and writes the formatted text to stdout. The
+//! formatted lines are matched back up with the tangled lines using a diff, so that they still
+//! know where in the .lit file they came from.
+
+use super::{TangledLine, Origin};
+use super::diff;
+use parser::{LineEnding};
+
+use subprocess;
+
+/// Format some tangled lines. If the formatter fails, the lines are left as they were.
+pub fn format_lines<'a>(command: &str, name: &str, lines: Vec<TangledLine<'a>>, line_ending: LineEnding) -> Vec<TangledLine<'a>> {
+    let mut text = String::new();
+    for line in lines.iter() {
+        text.push_str(&line.text);
+        text.push_str(line_ending.as_str());
+    }
+
+    trace!("Formatting \"{}\" with \"{}\"...", name, command);
+    let result = subprocess::Exec::shell(command)
+        .stdin(text.as_str())
+        .stdout(subprocess::Redirection::Pipe)
+        .stderr(subprocess::Redirection::Pipe)
+        .capture();
+
+    let formatted = match result {
+        Ok(ref capture) if capture.exit_status.success() => capture.stdout_str(),
+        Ok(capture) => {
+            warn!("The formatter \"{}\" failed on \"{}\", so it won't be formatted: {}", command, name, capture.stderr_str().trim_end());
+            return lines;
+        },
+        Err(err) => {
+            warn!("The formatter \"{}\" couldn't be run on \"{}\", so it won't be formatted: {}", command, name, err);
+            return lines;
+        },
+    };
+
+    let formatted_lines : Vec<&str> = formatted.lines().collect();
+    trace!("Finished formatting \"{}\"", name);
+
+    realign(lines, &formatted_lines)
+}
+
+/// Give each formatted line the origin of the tangled line that it came from
+fn realign<'a>(lines: Vec<TangledLine<'a>>, formatted_lines: &[&str]) -> Vec<TangledLine<'a>> {
+    // Formatters mostly move text around within a line, so lines are compared without their
+    // indentation
+    let trimmed : Vec<&str> = lines.iter().map(|line| line.text.trim()).collect();
+    let formatted_trimmed : Vec<&str> = formatted_lines.iter().map(|line| line.trim()).collect();
+
+    let hunks = diff::diff(&trimmed, &formatted_trimmed);
+
+    // For each formatted line, the tangled line that it came from
+    let mut sources = Vec::with_capacity(formatted_lines.len());
+    let mut old_position = 0;
+
+    for hunk in hunks.iter() {
+        sources.extend((old_position..hunk.old.start).map(Some));
+
+        // Changed lines are paired up in order, with any extras taking after the last line
+        sources.extend((0..hunk.new.len()).map(|index| if hunk.old.is_empty() {
+            None
+        } else {
+            Some(hunk.old.start + index.min(hunk.old.len() - 1))
+        }));
+
+        old_position = hunk.old.end;
+    }
+    sources.extend((old_position..lines.len()).map(Some));
+
+    formatted_lines.iter().zip(sources)
+        .map(|(formatted_line, source)| TangledLine {
+            text: (*formatted_line).to_owned(),
+            origin: source.and_then(|source| realign_origin(&lines[source], formatted_line)),
+        })
+        .collect()
+}
+
+fn realign_origin<'a>(line: &TangledLine<'a>, formatted_line: &str) -> Option<Origin<'a>> {
+    let origin = line.origin.as_ref()?;
+    let indentation = |text: &str| text.len() - text.trim_start().len();
+
+    Some(Origin {
+        lit_line: origin.lit_line,
+        // The .lit text moves along with the indentation
+        offset: (origin.offset + indentation(formatted_line)).saturating_sub(indentation(&line.text)),
+        block: origin.block,
+        expansion_chain: origin.expansion_chain.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{realign};
+    use super::super::{Origin, TangledLine};
+
+    fn line(text: &str, lit_line: Option<usize>) -> TangledLine<'static> {
+        TangledLine {
+            text: text.to_owned(),
+            origin: Some(Origin {
+                lit_line,
+                offset: 0,
+                block: "main.rs",
+                expansion_chain: vec!["main.rs"],
+            }),
+        }
+    }
+
+    /// The .lit line and offset of each line
+    fn origins(lines: &[TangledLine]) -> Vec<Option<(Option<usize>, usize)>> {
+        lines.iter().map(|line| line.origin.as_ref().map(|origin| (origin.lit_line, origin.offset))).collect()
+    }
+
+    #[test]
+    fn formatted_lines_keep_their_origins() {
+        let lines = vec![
+            line("// main.rs", None),
+            line("fn main() {", Some(10)),
+            line("", Some(11)),
+            line("let x = 1;", Some(12)),
+            line("let y = (1, 2);", Some(13)),
+            line("}", Some(14)),
+        ];
+        let formatted = [
+            "// main.rs",
+            "use std::io;",
+            "fn main() {",
+            "    let x = 1;",
+            "    let y = (",
+            "        1, 2);",
+            "}",
+        ];
+
+        let realigned = realign(lines, &formatted);
+
+        let texts : Vec<&str> = realigned.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, formatted);
+        assert_eq!(origins(&realigned), vec![
+            Some((None, 0)),
+            // Inserted by the formatter
+            None,
+            Some((Some(10), 0)),
+            // The blank line was deleted, and the indentation added moves the .lit text along
+            Some((Some(12), 4)),
+            // A line split in two comes from the same .lit line
+            Some((Some(13), 4)),
+            Some((Some(13), 8)),
+            Some((Some(14), 0)),
+        ]);
+    }
+}
@@ -0,0 +1,590 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+mod banner;
+mod compile;
+mod diff;
+mod format;
+mod region;
+mod source_map;
+pub use self::source_map::source_map_path;
+mod untangle;
+pub use self::untangle::untangle_blocks;
+
+use parser::{FormatFn, CompilerSettings, ExpansionMode, LineEnding};
+use link::{LineSegment, LinkedLine};
+use output;
+use output::canon::{CanonicalCodeBlock, BlockMap, Fragment, FragmentKind};
+
+use std::collections::{BTreeMap};
+use std::path::{Component, PathBuf, Path};
+use std::io::{Write};
+
+pub fn tangle_blocks<'a>(settings: Settings<'a>,
+                         canonical_code_blocks: &BlockMap) -> output::Result<()> {
+    trace!("Starting the tangle...");
+    let file_blocks : Vec<_> = canonical_code_blocks.iter()
+        .filter(|(_, block)| block.is_file() && block.print_to_tangle())
+        .collect();
+
+    if settings.global_settings.compile {
+        // To avoid cluttering a workspace during linting, we do not produce the tangle output when
+        // compiling
+        if !file_blocks.is_empty() {
+            compile::compile_blocks(&settings, &file_blocks, canonical_code_blocks)?;
+        }
+    } else {
+        // Blocks that go into regions of the same file are spliced in together
+        let mut region_blocks = BTreeMap::new();
+
+        for (name, block) in file_blocks {
+            if let (file_name, Some(region)) = region::split_region(name) {
+                region_blocks.entry(file_name).or_insert_with(Vec::new).push((region, name, block));
+                continue;
+            }
+
+            let output_file_path = output_path(&settings.global_settings.out_dir,
+                                               settings.global_settings.allow_outside_out_dir,
+                                               &settings.relative_directory.join(name))?;
+
+            info!("Found a file block \"{}\", writing to \"{}\"", name, output_file_path.to_string_lossy());
+            let lines = tangle_file(&settings, name, block, canonical_code_blocks);
+
+            // Print the file out
+            if let Some(parent) = output_file_path.parent() {
+                std::fs::DirBuilder::new().recursive(true).create(parent)?;
+            }
+
+            let mut contents = Vec::new();
+            print_lines(&mut contents, &lines, settings.line_ending)?;
+
+            if !settings.global_settings.force {
+                banner::check_for_hand_edits(&output_file_path, &contents)?;
+            }
+            output::write_if_changed(&output_file_path, &contents)?;
+
+            write_source_map(&settings, &output_file_path, name, &lines)?;
+        }
+
+        for (file_name, regions) in region_blocks {
+            let output_file_path = output_path(&settings.global_settings.out_dir,
+                                               settings.global_settings.allow_outside_out_dir,
+                                               &settings.relative_directory.join(file_name))?;
+
+            info!("Found {} region block(s) for \"{}\", writing to \"{}\"", regions.len(), file_name, output_file_path.to_string_lossy());
+            let existing = std::fs::read_to_string(&output_file_path).map_err(|err| {
+                error!("Couldn't read \"{}\" to tangle regions into it: {}", output_file_path.to_string_lossy(), err);
+                output::Error::from(err)
+            })?;
+
+            let tangled_regions = regions.into_iter()
+                .map(|(region, name, block)| (region, tangle_block(&settings, name, block, canonical_code_blocks, &[])))
+                .collect();
+            let lines = region::splice_regions(&settings, &output_file_path, &existing, tangled_regions)?;
+
+            // Hand-written files keep their own line endings
+            let line_ending = LineEnding::detect(&existing);
+            let mut contents = Vec::new();
+            print_lines(&mut contents, &lines, line_ending)?;
+            if !existing.ends_with('\n') {
+                contents.truncate(contents.len().saturating_sub(line_ending.as_str().len()));
+            }
+
+            output::write_if_changed(&output_file_path, &contents)?;
+
+            write_source_map(&settings, &output_file_path, file_name, &lines)?;
+        }
+    }
+
+    trace!("Finished the tangle");
+    Ok(())
+}
+
+fn write_source_map(settings: &Settings, output_file_path: &Path, name: &str, lines: &[TangledLine]) -> output::Result<()> {
+    if settings.global_settings.source_map {
+        let source_map_path = source_map::source_map_path(output_file_path);
+        info!("Writing the source map for \"{}\" to \"{}\"", name, source_map_path.to_string_lossy());
+
+        let mut source_map = Vec::new();
+        source_map::print_source_map(&mut source_map, settings.lit_file, name, lines)?;
+        output::write_if_changed(&source_map_path, &source_map)?;
+    }
+
+    Ok(())
+}
+
+pub struct Settings<'borrow> {
+    pub global_settings: &'borrow Globals,
+    pub lit_file: &'borrow Path,
+    pub relative_directory: &'borrow Path,
+    pub line_numbers: Option<&'borrow FormatFn<usize>>,
+    pub comment_formatter: Option<&'borrow FormatFn<String>>,
+    pub expansion_mode: ExpansionMode,
+    pub compiler: &'borrow Option<CompilerSettings>,
+    pub line_ending: LineEnding,
+    pub strip_trailing_whitespace: bool,
+    pub final_newline: bool,
+    pub banner: Option<&'borrow str>,
+    pub block_markers: bool,
+    pub doc_comment_formatter: Option<&'borrow FormatFn<String>>,
+    pub prose_comments: bool,
+    pub formatter: Option<&'borrow str>,
+}
+
+#[derive(Default)]
+pub struct Globals {
+    pub compile: bool,
+    pub line_numbers: Option<FormatFn<usize>>,
+    pub out_dir: PathBuf,
+    pub source_map: bool,
+    pub untangle: bool,
+    /// Allow file blocks to be written outside of the output directory
+    pub allow_outside_out_dir: bool,
+    /// The line ending for files which don't pick one - otherwise, each file uses the same line
+    /// ending as its .lit file
+    pub line_ending: Option<LineEnding>,
+    pub strip_trailing_whitespace: bool,
+    /// End every file with exactly one newline, dropping any blank lines at the end
+    pub final_newline: bool,
+    /// The banner for files which don't set their own
+    pub banner: Option<String>,
+    /// Overwrite files even if they've been edited by hand
+    pub force: bool,
+    /// Mark the beginning and end of every expanded block, and where it was written
+    pub block_markers: bool,
+    /// The formatter for files which don't set their own
+    pub formatter: Option<String>,
+}
+
+/// Find where a file should be tangled to, making sure that it stays inside the output directory
+/// - either by climbing out of it with `..`, or by following a symlink out of it
+fn output_path(out_dir: &Path, allow_outside_out_dir: bool, relative_path: &Path) -> output::Result<PathBuf> {
+    if allow_outside_out_dir {
+        return Ok(out_dir.join(relative_path));
+    }
+
+    let escapes = || {
+        error!("\"{}\" would be written outside of the output directory \"{}\" - pass --allow-outside-out-dir if this is intended",
+               relative_path.to_string_lossy(),
+               out_dir.to_string_lossy());
+        output::Error::UnsafeOutputPath(relative_path.to_path_buf())
+    };
+
+    let mut normalised = PathBuf::new();
+    for component in relative_path.components() {
+        match component {
+            Component::Normal(part) => normalised.push(part),
+            Component::CurDir => {},
+            Component::ParentDir => if !normalised.pop() {
+                return Err(escapes());
+            },
+            Component::RootDir | Component::Prefix(_) => return Err(escapes()),
+        }
+    }
+
+    std::fs::DirBuilder::new().recursive(true).create(out_dir)?;
+    let canonical_out_dir = out_dir.canonicalize()?;
+
+    // Any part of the path that already exists might be a symlink
+    let mut existing = out_dir.to_path_buf();
+    for part in normalised.iter() {
+        existing.push(part);
+
+        if existing.symlink_metadata().is_err() {
+            break;
+        }
+
+        let resolved = existing.canonicalize().map_err(|_| escapes())?;
+        if !resolved.starts_with(&canonical_out_dir) {
+            return Err(escapes());
+        }
+    }
+
+    Ok(out_dir.join(normalised))
+}
+
+/// A single line of tangled output
+pub struct TangledLine<'a> {
+    pub text: String,
+    pub origin: Option<Origin<'a>>,
+}
+
+/// Where a line of tangled output came from
+pub struct Origin<'a> {
+    /// The line in the .lit file, if the output line is a copy of one
+    pub lit_line: Option<usize>,
+    /// The byte offset in the output line at which the .lit line's text starts
+    pub offset: usize,
+    /// The code block that the line belongs to
+    pub block: &'a str,
+    /// The chain of blocks that were expanded to reach this line, starting from the file block
+    pub expansion_chain: Vec<&'a str>,
+}
+
+fn tangle_file<'a>(settings: &Settings,
+                   name: &'a str,
+                   file_block: &CanonicalCodeBlock<'a>,
+                   blocks: &BlockMap<'a>) -> Vec<TangledLine<'a>> {
+    trace!("Tangling \"{}\"...", name);
+    let mut lines = tangle_block(settings, name, file_block, blocks, &[]);
+
+    if let Some(formatter) = settings.formatter {
+        lines = format::format_lines(formatter, name, lines, settings.line_ending);
+    }
+
+    if settings.strip_trailing_whitespace {
+        for line in lines.iter_mut() {
+            let trimmed_length = line.text.trim_end().len();
+            line.text.truncate(trimmed_length);
+        }
+    }
+
+    if settings.final_newline {
+        while lines.last().is_some_and(|line| line.text.is_empty()) {
+            lines.pop();
+        }
+    }
+
+    if settings.block_markers && settings.comment_formatter.is_none() {
+        warn!("\"{}\" has no @comment_type, so \"{}\" can't be given block markers", settings.lit_file.to_string_lossy(), name);
+    }
+
+    if let Some(banner) = settings.banner {
+        match settings.comment_formatter {
+            Some(comment_formatter) => banner::add_banner(settings, banner, comment_formatter, &mut lines),
+            None => warn!("\"{}\" has no @comment_type, so \"{}\" can't be given a banner", settings.lit_file.to_string_lossy(), name),
+        }
+    }
+
+    trace!("Finished tangling \"{}\"", name);
+    lines
+}
+
+fn tangle_block<'a>(settings: &Settings,
+                    name: &'a str,
+                    block: &CanonicalCodeBlock<'a>,
+                    blocks: &BlockMap<'a>,
+                    parent_chain: &[&'a str]) -> Vec<TangledLine<'a>> {
+    let mut expansion_chain = parent_chain.to_vec();
+    expansion_chain.push(name);
+
+    let origin = |lit_line| Some(Origin {
+        lit_line,
+        offset: 0,
+        block: name,
+        expansion_chain: expansion_chain.clone(),
+    });
+
+    let comment = |text: String| settings.comment_formatter.map(|comment_formatter| TangledLine {
+        text: comment_formatter(text),
+        origin: origin(None),
+    });
+
+    let mut tangled_lines = Vec::new();
+
+    // Block markers take the place of the header
+    if block.print_header() && !settings.block_markers {
+        tangled_lines.extend(comment(name.to_string()));
+    }
+
+    let mut remaining_lines = block.contents();
+
+    for fragment in block.fragments() {
+        let (fragment_lines, rest) = remaining_lines.split_at(fragment.length);
+        remaining_lines = rest;
+
+        if settings.block_markers {
+            tangled_lines.extend(comment(block_marker(BEGIN_BLOCK_MARKER, settings, name, fragment)));
+        }
+
+        if settings.prose_comments || block.doc_comment() {
+            tangled_lines.extend(prose_comments(settings, name, &fragment.prose, &origin));
+        }
+
+        for line in fragment_lines {
+            tangle_line(settings, line, blocks, &expansion_chain, origin(Some(line.line_number())), &mut tangled_lines);
+        }
+
+        if settings.block_markers {
+            tangled_lines.extend(comment(block_marker(END_BLOCK_MARKER, settings, name, fragment)));
+        }
+    }
+
+    tangled_lines
+}
+
+fn tangle_line<'a>(settings: &Settings,
+                   line: &LinkedLine<'a>,
+                   blocks: &BlockMap<'a>,
+                   expansion_chain: &[&'a str],
+                   line_origin: Option<Origin<'a>>,
+                   tangled_lines: &mut Vec<TangledLine<'a>>) {
+    // A line which asks for indentation-aware expansion anywhere is expanded entirely that way
+    let indent_mode = line.segments().any(|segment| match segment {
+        LineSegment::Link { mode, .. } => mode.unwrap_or(settings.expansion_mode) == ExpansionMode::Indent,
+        LineSegment::Text(_) => false,
+    });
+
+    if indent_mode {
+        tangled_lines.extend(tangle_indented_line(settings, line, blocks, expansion_chain, line_origin));
+        return;
+    }
+
+    let mut printed_link = false;
+
+    for (pre_link, link, post_link) in line.split_links() {
+        printed_link = true;
+
+        let prependix = pre_link.concat();
+        let appendix = post_link.concat();
+
+        // Block markers only take on the indentation of the line, so that they stay comments
+        let indentation = &prependix[..prependix.len() - prependix.trim_start().len()];
+
+        let sub_lines = tangle_block(settings, link, blocks.get(link).unwrap(), blocks, expansion_chain);
+        tangled_lines.extend(sub_lines.into_iter().map(|sub_line| if settings.block_markers && is_generated(&sub_line) {
+            surround(sub_line, indentation, "")
+        } else {
+            surround(sub_line, &prependix, &appendix)
+        }));
+    }
+
+    if !printed_link {
+        tangled_lines.push(TangledLine {
+            text: line.get_text().to_owned(),
+            origin: line_origin,
+        });
+    }
+}
+
+/// Write prose into the code output as comments
+fn prose_comments<'a>(settings: &Settings,
+                      name: &str,
+                      prose: &[LinkedLine],
+                      origin: &dyn Fn(Option<usize>) -> Option<Origin<'a>>) -> Vec<TangledLine<'a>> {
+    let doc_comment_formatter = match settings.doc_comment_formatter {
+        Some(doc_comment_formatter) => doc_comment_formatter,
+        None => {
+            if !prose.is_empty() {
+                warn!("\"{}\" has no @comment_type or @doc_comment_type, so the prose for \"{}\" can't be written as comments",
+                      settings.lit_file.to_string_lossy(), name);
+            }
+            return Vec::new();
+        },
+    };
+
+    let is_blank = |line: &&LinkedLine| line.get_text().trim().is_empty();
+    let start = prose.iter().position(|line| !is_blank(&line)).unwrap_or(prose.len());
+    let end = prose.iter().rposition(|line| !is_blank(&line)).map_or(start, |end| end + 1);
+
+    prose[start..end].iter()
+        .map(|line| TangledLine {
+            text: doc_comment_formatter(line.get_text().trim_end().to_owned()).trim_end().to_owned(),
+            origin: origin(None),
+        })
+        .collect()
+}
+
+const BEGIN_BLOCK_MARKER : &str = "lit:block-begin";
+const END_BLOCK_MARKER : &str = "lit:block-end";
+
+/// Describe where a piece of a block was written, like
+/// `lit:block-begin body [lit/main.lit, section 2 "The body", appended]`
+fn block_marker(marker: &str, settings: &Settings, name: &str, fragment: &Fragment) -> String {
+    let section = match fragment.section_name {
+        Some(section_name) => format!("section {} \"{}\"", fragment.section, section_name),
+        None => format!("section {}", fragment.section),
+    };
+
+    let kind = match fragment.kind {
+        FragmentKind::Definition => "defined",
+        FragmentKind::Append => "appended",
+        FragmentKind::Redefinition => "redefined",
+    };
+
+    format!("{} {} [{}, {}, {}]", marker, name, settings.lit_file.to_string_lossy(), section, kind)
+}
+
+/// Expand a line so that each referenced block is spliced in where the reference was, with its
+/// later lines indented to match the line
+fn tangle_indented_line<'a>(settings: &Settings,
+                            line: &LinkedLine<'a>,
+                            blocks: &BlockMap<'a>,
+                            expansion_chain: &[&'a str],
+                            line_origin: Option<Origin<'a>>) -> Vec<TangledLine<'a>> {
+    let text = line.get_text();
+    let indentation = &text[..text.len() - text.trim_start().len()];
+
+    let mut tangled_lines = Vec::new();
+    // Generated lines which follow the last line of code from a block, which have to wait until
+    // the rest of the line has been finished
+    let mut trailing_lines = Vec::new();
+    let mut current = TangledLine {
+        text: String::new(),
+        origin: None,
+    };
+
+    for segment in line.segments() {
+        match segment {
+            LineSegment::Text(text) => current.text.push_str(text),
+            LineSegment::Link { name, .. } => {
+                let sub_lines = tangle_block(settings, name, blocks.get(name).unwrap(), blocks, expansion_chain);
+                let last_code_line = sub_lines.iter().rposition(|sub_line| !is_generated(sub_line));
+                let mut started = false;
+
+                for (index, sub_line) in sub_lines.into_iter().enumerate() {
+                    // Generated lines, like headers, go on lines of their own
+                    if is_generated(&sub_line) {
+                        let sub_line = surround(sub_line, indentation, "");
+
+                        if last_code_line.is_none_or(|last_code_line| index > last_code_line) {
+                            trailing_lines.push(sub_line);
+                        } else if started {
+                            let finished = std::mem::replace(&mut current, TangledLine {
+                                text: indentation.to_owned(),
+                                origin: None,
+                            });
+                            tangled_lines.push(finished);
+                            tangled_lines.push(sub_line);
+                            started = false;
+                        } else {
+                            tangled_lines.push(sub_line);
+                        }
+                        continue;
+                    }
+
+                    if started {
+                        let finished = std::mem::replace(&mut current, TangledLine {
+                            text: indentation.to_owned(),
+                            origin: None,
+                        });
+                        tangled_lines.push(finished);
+                    }
+
+                    let sub_line = surround(sub_line, &current.text, "");
+                    current = sub_line;
+                    started = true;
+                }
+            },
+        }
+    }
+
+    if current.origin.is_none() {
+        current.origin = line_origin;
+    }
+    tangled_lines.push(current);
+    tangled_lines.extend(trailing_lines);
+
+    tangled_lines
+}
+
+/// Whether a line was made by rliterate, rather than copied from the .lit file
+fn is_generated(line: &TangledLine) -> bool {
+    line.origin.as_ref().is_none_or(|origin| origin.lit_line.is_none())
+}
+
+fn surround<'a>(line: TangledLine<'a>, prependix: &str, appendix: &str) -> TangledLine<'a> {
+    TangledLine {
+        text: format!("{}{}{}", prependix, line.text, appendix),
+        origin: line.origin.map(|origin| Origin {
+            offset: origin.offset + prependix.len(),
+            ..origin
+        }),
+    }
+}
+
+fn print_lines<W: Write>(file: &mut W, lines: &[TangledLine], line_ending: LineEnding) -> output::Result<()> {
+    for line in lines {
+        write!(file, "{}{}", line.text, line_ending.as_str())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{output_path};
+    use output;
+    use output::{ScratchDir};
+
+    use std::path::{Path, PathBuf};
+    #[cfg(unix)]
+    use std::{fs, os};
+
+    fn is_unsafe(result: output::Result<PathBuf>, relative_path: &str) -> bool {
+        match result {
+            Err(output::Error::UnsafeOutputPath(path)) => path == Path::new(relative_path),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn paths_inside_the_output_directory_are_kept() {
+        let out_dir = ScratchDir::new("test").unwrap();
+
+        let path = output_path(out_dir.path(), false, Path::new("src/../lib/./main.rs")).unwrap();
+        assert_eq!(path, out_dir.path().join("lib/main.rs"));
+    }
+
+    #[test]
+    fn climbing_out_of_the_output_directory_is_rejected() {
+        let out_dir = ScratchDir::new("test").unwrap();
+
+        assert!(is_unsafe(output_path(out_dir.path(), false, Path::new("../x")), "../x"));
+        assert!(is_unsafe(output_path(out_dir.path(), false, Path::new("src/../../x")), "src/../../x"));
+    }
+
+    #[test]
+    fn absolute_paths_are_rejected() {
+        let out_dir = ScratchDir::new("test").unwrap();
+
+        assert!(is_unsafe(output_path(out_dir.path(), false, Path::new("/tmp/x")), "/tmp/x"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_output_directory_are_rejected() {
+        let out_dir = ScratchDir::new("test").unwrap();
+        let outside = ScratchDir::new("test").unwrap();
+        os::unix::fs::symlink(outside.path(), out_dir.path().join("link")).unwrap();
+
+        assert!(is_unsafe(output_path(out_dir.path(), false, Path::new("link/x")), "link/x"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_inside_the_output_directory_are_followed() {
+        let out_dir = ScratchDir::new("test").unwrap();
+        fs::create_dir(out_dir.path().join("real")).unwrap();
+        os::unix::fs::symlink(out_dir.path().join("real"), out_dir.path().join("link")).unwrap();
+
+        let path = output_path(out_dir.path(), false, Path::new("link/x")).unwrap();
+        assert_eq!(path, out_dir.path().join("link/x"));
+    }
+
+    #[test]
+    fn paths_outside_the_output_directory_can_be_allowed() {
+        let out_dir = ScratchDir::new("test").unwrap();
+
+        let path = output_path(out_dir.path(), true, Path::new("../x")).unwrap();
+        assert_eq!(path, out_dir.path().join("../x"));
+    }
+}
@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Regions, which let a block be tangled into part of a hand-written file.
+//!
+//! A block named `main.rs#{parse_args}` is tangled into the region of `main.rs` that is marked out
+//! by a `lit:begin parse_args` line and the next `lit:end` line, usually in comments:
+//!
+//! ```text
+//! // lit:begin parse_args
+//! ...
+//! // lit:end
+//! ```
+//!
+//! Only the lines between the markers are replaced, and they take on the indentation of the
+//! `lit:begin` line. The rest of the file is left as it is.
+//!
+//! The region's name goes in braces so that a file block with a `#` in its name, like
+//! `C#/Program.cs`, is still a whole file.
+//!
+//! The file belongs to whoever wrote it, not to rliterate, so the steps that work on a whole tangled
+//! file - the `@formatter`, the `@banner` and the check for hand edits - are skipped. Trailing
+//! whitespace is still stripped from the lines of the regions.
+
+use super::{Settings, TangledLine, surround};
+use output;
+
+use std::path::{Path};
+
+const REGION_START : &str = "#{";
+const REGION_END : char = '}';
+const BEGIN_MARKER : &str = "lit:begin";
+const END_MARKER : &str = "lit:end";
+
+/// Split a block name into the file that it is tangled to, and the region of that file, if any
+pub fn split_region(name: &str) -> (&str, Option<&str>) {
+    match name.rfind(REGION_START) {
+        Some(index) if name.ends_with(REGION_END) => {
+            (&name[..index], Some(&name[index + REGION_START.len()..name.len() - 1]))
+        },
+        _ => (name, None),
+    }
+}
+
+/// Replace the marked regions of a file's contents with tangled lines. The rest of the file is
+/// kept, as lines which don't come from the .lit file.
+pub fn splice_regions<'a>(settings: &Settings,
+                          path: &Path,
+                          existing: &str,
+                          mut regions: Vec<(&str, Vec<TangledLine<'a>>)>) -> output::Result<Vec<TangledLine<'a>>> {
+    let untouched = |line: &str| TangledLine {
+        text: line.to_owned(),
+        origin: None,
+    };
+
+    let mut lines = Vec::new();
+    let mut found = vec![false; regions.len()];
+    let mut existing_lines = existing.lines();
+
+    while let Some(line) = existing_lines.next() {
+        lines.push(untouched(line));
+
+        let region_index = match begin_marker(line).and_then(|region| regions.iter().position(|(name, _)| *name == region)) {
+            Some(region_index) => region_index,
+            None => continue,
+        };
+
+        let (region, ref mut region_lines) = regions[region_index];
+        if found[region_index] {
+            error!("The region \"{}\" is in \"{}\" more than once", region, path.to_string_lossy());
+            return Err(output::Error::DuplicateRegion(path.to_path_buf(), region.to_owned()));
+        }
+        trace!("Found the region \"{}\" in \"{}\"", region, path.to_string_lossy());
+
+        // The old contents of the region are dropped
+        let end_marker = existing_lines.by_ref().find(|line| line.contains(END_MARKER)).ok_or_else(|| {
+            error!("The region \"{}\" in \"{}\" has no `{}` line to end it", region, path.to_string_lossy(), END_MARKER);
+            output::Error::MissingRegion(path.to_path_buf(), region.to_owned())
+        })?;
+
+        let indentation = &line[..line.len() - line.trim_start().len()];
+        lines.extend(region_lines.drain(..).map(|region_line| if region_line.text.is_empty() {
+            region_line
+        } else {
+            surround(region_line, indentation, "")
+        }));
+        lines.push(untouched(end_marker));
+
+        found[region_index] = true;
+    }
+
+    if let Some(missing) = found.iter().position(|found| !found) {
+        let region = regions[missing].0;
+        error!("Couldn't find the region \"{}\" in \"{}\" - it needs to start with a `{} {}` line and end with a `{}` line",
+               region, path.to_string_lossy(), BEGIN_MARKER, region, END_MARKER);
+        return Err(output::Error::MissingRegion(path.to_path_buf(), region.to_owned()));
+    }
+
+    if settings.strip_trailing_whitespace {
+        for line in lines.iter_mut().filter(|line| line.origin.is_some()) {
+            let trimmed_length = line.text.trim_end().len();
+            line.text.truncate(trimmed_length);
+        }
+    }
+
+    Ok(lines)
+}
+
+fn begin_marker(line: &str) -> Option<&str> {
+    let marker = line.find(BEGIN_MARKER)?;
+    line[marker + BEGIN_MARKER.len()..].split_whitespace().next()
+}
@@ -0,0 +1,172 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Source map sidecars, which record where each line of a tangled file came from.
+//!
+//! For an output file `main.rs`, the map is written to `main.rs.litmap.json`, in the form
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "file": "main.rs",
+//!   "lines": [
+//!     { "line": 1, "source": "lit/main.lit", "source_line": 12, "column": 1, "block": "main.rs", "expansion_chain": ["main.rs"] }
+//!   ]
+//! }
+//! ```
+//!
+//! Lines and columns are 1-based. `source_line` is `null` for generated lines, like block headers,
+//! and `column` is the column of the output line at which the .lit line's text starts.
+
+use super::TangledLine;
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const SOURCE_MAP_VERSION: usize = 1;
+
+pub fn source_map_path(output_file_path: &Path) -> PathBuf {
+    let mut file_name = output_file_path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".litmap.json");
+    output_file_path.with_file_name(file_name)
+}
+
+pub fn print_source_map<W: Write>(file: &mut W, lit_file: &Path, output_name: &str, lines: &[TangledLine]) -> io::Result<()> {
+    let source = json_string(&lit_file.to_string_lossy());
+
+    writeln!(file, "{{")?;
+    writeln!(file, "  \"version\": {},", SOURCE_MAP_VERSION)?;
+    writeln!(file, "  \"file\": {},", json_string(output_name))?;
+    write!(file, "  \"lines\": [")?;
+
+    let mut first = true;
+    for (index, line) in lines.iter().enumerate() {
+        let origin = match line.origin {
+            Some(ref origin) => origin,
+            None => continue,
+        };
+
+        if !first {
+            write!(file, ",")?;
+        }
+        first = false;
+
+        let chain : Vec<String> = origin.expansion_chain.iter().map(|name| json_string(name)).collect();
+
+        write!(file, "\n    {{ \"line\": {}, \"source\": {}, \"source_line\": {}, \"column\": {}, \"block\": {}, \"expansion_chain\": [{}] }}",
+               index + 1,
+               source,
+               origin.lit_line.map_or("null".to_owned(), |line| line.to_string()),
+               origin.offset + 1,
+               json_string(origin.block),
+               chain.join(", "))?;
+    }
+
+    writeln!(file, "\n  ]")?;
+    writeln!(file, "}}")?;
+
+    Ok(())
+}
+
+fn json_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+
+    for character in string.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            control if (control as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", control as u32)),
+            other => escaped.push(other),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use super::{SOURCE_MAP_VERSION, print_source_map};
+    use super::super::{Origin, TangledLine};
+
+    use std::path::{Path};
+
+    #[test]
+    fn source_maps_are_valid_json() {
+        let lines = vec![
+            TangledLine {
+                text: "// \"weird\\name\".rs".to_owned(),
+                origin: Some(Origin {
+                    lit_line: None,
+                    offset: 0,
+                    block: "\"weird\\name\".rs",
+                    expansion_chain: vec!["\"weird\\name\".rs"],
+                }),
+            },
+            TangledLine {
+                text: "unmapped".to_owned(),
+                origin: None,
+            },
+            TangledLine {
+                text: "    let grüße = 1;".to_owned(),
+                origin: Some(Origin {
+                    lit_line: Some(7),
+                    offset: 4,
+                    block: "tab\there, ünïcödé",
+                    expansion_chain: vec!["\"weird\\name\".rs", "tab\there, ünïcödé"],
+                }),
+            },
+        ];
+
+        let mut source_map = Vec::new();
+        print_source_map(&mut source_map, Path::new("lit/\"quoted\" \\ dir/ünïcödé.lit"), "\"weird\\name\".rs", &lines).unwrap();
+        let source_map : serde_json::Value = serde_json::from_slice(&source_map).unwrap();
+
+        assert_eq!(source_map, serde_json::json!({
+            "version": SOURCE_MAP_VERSION,
+            "file": "\"weird\\name\".rs",
+            "lines": [
+                {
+                    "line": 1,
+                    "source": "lit/\"quoted\" \\ dir/ünïcödé.lit",
+                    "source_line": null,
+                    "column": 1,
+                    "block": "\"weird\\name\".rs",
+                    "expansion_chain": ["\"weird\\name\".rs"],
+                },
+                {
+                    "line": 3,
+                    "source": "lit/\"quoted\" \\ dir/ünïcödé.lit",
+                    "source_line": 7,
+                    "column": 5,
+                    "block": "tab\there, ünïcödé",
+                    "expansion_chain": ["\"weird\\name\".rs", "tab\there, ünïcödé"],
+                },
+            ],
+        }));
+    }
+}
@@ -0,0 +1,233 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Untangling, which copies edits made to tangled files back into the .lit file they came from.
+
+use super::{output_path, region, tangle_file, Settings, TangledLine};
+use super::diff::{self, Hunk};
+use output;
+use output::canon::{BlockMap};
+
+use std::collections::{HashMap};
+use std::fs;
+
+pub fn untangle_blocks<'a>(settings: Settings<'a>,
+                           canonical_code_blocks: &BlockMap) -> output::Result<()> {
+    trace!("Starting the untangle...");
+    let lit_contents = fs::read_to_string(settings.lit_file)?;
+    let lit_lines : Vec<&str> = lit_contents.lines().collect();
+
+    // Everything is tangled up front, so that we know how many times each .lit line is used
+    let tangled_files : Vec<(&str, Vec<TangledLine>)> = canonical_code_blocks.iter()
+        .filter(|(_, block)| block.is_file() && block.print_to_tangle())
+        .filter(|(name, _)| if let (_, Some(_)) = region::split_region(name) {
+            warn!("\"{}\" is tangled into a region of a hand-written file, which can't be untangled", name);
+            false
+        } else {
+            true
+        })
+        .map(|(name, block)| (*name, tangle_file(&settings, name, block, canonical_code_blocks)))
+        .collect();
+
+    let mut uses = HashMap::new();
+    for lit_line in tangled_files.iter().flat_map(|(_, lines)| lines.iter()).filter_map(lit_line) {
+        *uses.entry(lit_line).or_insert(0_usize) += 1;
+    }
+
+    let mut edits = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for (name, fresh_lines) in tangled_files.iter() {
+        let output_file_path = output_path(&settings.global_settings.out_dir,
+                                           settings.global_settings.allow_outside_out_dir,
+                                           &settings.relative_directory.join(name))?;
+
+        if !output_file_path.is_file() {
+            warn!("\"{}\" has not been tangled yet, so there is nothing to untangle", output_file_path.to_string_lossy());
+            continue;
+        }
+
+        trace!("Comparing \"{}\" against a fresh tangle...", output_file_path.to_string_lossy());
+        let edited_contents = fs::read_to_string(&output_file_path)?;
+        let edited_lines : Vec<&str> = edited_contents.lines().collect();
+        let fresh_text : Vec<&str> = fresh_lines.iter().map(|line| line.text.as_str()).collect();
+
+        for hunk in diff::diff(&fresh_text, &edited_lines) {
+            match edit_for_hunk(&hunk, fresh_lines, &edited_lines, &lit_lines, &uses) {
+                Ok(edit) => {
+                    debug!("Untangling lines {}-{} of \"{}\" into line {} of \"{}\"",
+                           hunk.new.start + 1,
+                           hunk.new.end,
+                           output_file_path.to_string_lossy(),
+                           edit.start,
+                           settings.lit_file.to_string_lossy());
+                    edits.push(edit);
+                },
+                Err(reason) => conflicts.push(format!("{}:{}: {}", output_file_path.to_string_lossy(), hunk.new.start + 1, reason)),
+            }
+        }
+    }
+
+    edits.sort_by_key(|edit| (edit.start, edit.removed));
+    for pair in edits.windows(2) {
+        if pair[0].start + pair[0].removed > pair[1].start
+            || (pair[0].start == pair[1].start && pair[0].removed == 0 && pair[1].removed == 0) {
+            conflicts.push(format!("{}:{}: more than one edit changes this line",
+                                   settings.lit_file.to_string_lossy(),
+                                   pair[1].start));
+        }
+    }
+
+    if !conflicts.is_empty() {
+        for conflict in conflicts.iter() {
+            error!("Could not untangle {}", conflict);
+        }
+        return Err(output::Error::UntangleConflicts(conflicts.len()));
+    }
+
+    if edits.is_empty() {
+        info!("Found no edits to untangle into \"{}\"", settings.lit_file.to_string_lossy());
+    } else {
+        info!("Untangling {} edits into \"{}\"", edits.len(), settings.lit_file.to_string_lossy());
+        let line_ending = if lit_contents.contains("\r\n") { "\r\n" } else { "\n" };
+
+        let mut new_lines : Vec<&str> = lit_lines.clone();
+        // Applying the edits back-to-front keeps the earlier line numbers valid
+        for edit in edits.iter().rev() {
+            let start = edit.start - 1;
+            new_lines.splice(start..start + edit.removed, edit.inserted.iter().map(|line| line.as_str()));
+        }
+
+        let mut new_contents = new_lines.join(line_ending);
+        if lit_contents.ends_with('\n') {
+            new_contents.push_str(line_ending);
+        }
+
+        output::write_if_changed(settings.lit_file, new_contents.as_bytes())?;
+    }
+
+    trace!("Finished the untangle");
+    Ok(())
+}
+
+/// A change to the lines of a .lit file
+struct LitEdit {
+    /// The first line affected
+    start: usize,
+    /// How many lines are replaced, starting at `start`
+    removed: usize,
+    inserted: Vec<String>,
+}
+
+fn lit_line(line: &TangledLine) -> Option<usize> {
+    line.origin.as_ref().and_then(|origin| origin.lit_line)
+}
+
+fn edit_for_hunk(hunk: &Hunk,
+                 fresh_lines: &[TangledLine],
+                 edited_lines: &[&str],
+                 lit_lines: &[&str],
+                 uses: &HashMap<usize, usize>) -> Result<LitEdit, String> {
+    let replaced = &fresh_lines[hunk.old.clone()];
+
+    // The anchor is the tangled line that decides which block, and which fragment of that block,
+    // the edit belongs to. The neighbours are the tangled lines whose surrounding text the new
+    // lines should share
+    let (anchor, start, neighbours) = if replaced.is_empty() {
+        let above = hunk.old.start.checked_sub(1)
+            .map(|index| &fresh_lines[index])
+            .and_then(|line| lit_line(line).map(|number| (line, number)));
+        let below = fresh_lines.get(hunk.old.start)
+            .and_then(|line| lit_line(line).map(|number| (line, number)));
+
+        match (above, below) {
+            (Some((above, above_number)), Some((below, below_number))) if below_number == above_number + 1 => (above, above_number + 1, vec![above, below]),
+            (Some(_), Some(_)) => return Err("the inserted lines fall between two different code blocks".to_owned()),
+            (Some((above, above_number)), None) => (above, above_number + 1, vec![above]),
+            (None, Some((below, below_number))) => (below, below_number, vec![below]),
+            (None, None) => return Err("the inserted lines are not next to any code from the .lit file".to_owned()),
+        }
+    } else {
+        let first_number = lit_line(&replaced[0])
+            .ok_or_else(|| "the edit changes a line that was generated, not copied from the .lit file".to_owned())?;
+
+        for (offset, line) in replaced.iter().enumerate() {
+            match lit_line(line) {
+                None => return Err("the edit changes a line that was generated, not copied from the .lit file".to_owned()),
+                Some(number) if number != first_number + offset => return Err("the edit crosses a code block boundary".to_owned()),
+                Some(_) => {},
+            }
+        }
+
+        (&replaced[0], first_number, replaced.iter().collect())
+    };
+
+    // Unwrapping is safe, because the anchor was chosen for having a .lit line
+    let origin = anchor.origin.as_ref().unwrap();
+
+    let anchor_number = origin.lit_line.unwrap();
+    if uses.get(&anchor_number).is_some_and(|&count| count > 1) {
+        return Err(format!("block \"{}\" is used in more than one place, so the edit can't be copied back safely", origin.block));
+    }
+
+    let edges = neighbours.iter()
+        .map(|line| surroundings(line, lit_lines))
+        .collect::<Option<Vec<(&str, &str)>>>()
+        .ok_or_else(|| format!("the .lit file has changed since \"{}\" was tangled", origin.block))?;
+
+    // With indentation-aware expansion, the first and last lines of an expansion carry the text
+    // around the reference, and the lines in between only carry the indentation
+    let middle_prefix = edges[1.min(edges.len() - 1)].0;
+    let middle_suffix = edges[edges.len().saturating_sub(2)].1;
+
+    let new_lines = &edited_lines[hunk.new.clone()];
+    let inserted = new_lines.iter().enumerate()
+        .map(|(index, line)| {
+            let prefix = if index == 0 && !replaced.is_empty() { edges[0].0 } else { middle_prefix };
+            let suffix = if index + 1 == new_lines.len() && !replaced.is_empty() { edges[edges.len() - 1].1 } else { middle_suffix };
+
+            if line.len() >= prefix.len() + suffix.len() && line.starts_with(prefix) && line.ends_with(suffix) {
+                Ok(line[prefix.len()..line.len() - suffix.len()].to_owned())
+            } else {
+                Err(format!("the edit changes text that comes from outside of block \"{}\"", origin.block))
+            }
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+
+    Ok(LitEdit {
+        start,
+        removed: replaced.len(),
+        inserted,
+    })
+}
+
+/// The text that surrounds a .lit line in a tangled line, from the blocks that it was expanded into
+fn surroundings<'l>(line: &'l TangledLine, lit_lines: &[&str]) -> Option<(&'l str, &'l str)> {
+    let origin = line.origin.as_ref()?;
+    let lit_text = lit_lines.get(origin.lit_line?.checked_sub(1)?)?;
+    let end = origin.offset + lit_text.len();
+
+    if line.text.get(origin.offset..end) == Some(lit_text) {
+        Some((&line.text[..origin.offset], &line.text[end..]))
+    } else {
+        None
+    }
+}
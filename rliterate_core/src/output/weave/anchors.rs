@@ -0,0 +1,131 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Anchors, which give the parts of a woven file stable ids to link to.
+//!
+//! Ids are made from names rather than numbers, so that links to them keep working when the
+//! sections around them change.
+
+use super::markdown::{is_woven};
+use output::canon::{BlockMap};
+use link::{LinkedFile, LinkedBlock};
+
+use std::collections::{HashMap, HashSet};
+
+pub struct Anchors {
+    sections: HashMap<usize, String>,
+    /// Only blocks which are shown in the weave get an anchor
+    blocks: HashMap<String, String>,
+}
+
+impl Anchors {
+    pub fn build(file: &LinkedFile, block_map: &BlockMap) -> Self {
+        Anchors::build_with(file, block_map, &mut HashSet::new())
+    }
+
+    /// Build the anchors of a file without using any of the ids already `taken`, as when several
+    /// files are woven into one document
+    pub fn build_with(file: &LinkedFile, block_map: &BlockMap, taken: &mut HashSet<String>) -> Self {
+        let sections = section_anchors(file, taken);
+        let mut blocks = HashMap::new();
+
+        for section in file.sections.iter() {
+            for block in section.blocks.iter() {
+                if let LinkedBlock::Code { name, modifiers, .. } = block {
+                    if is_woven(name, *modifiers, block_map) && !blocks.contains_key(name.as_str()) {
+                        let slug = slugify(name);
+                        let slug = if slug.is_empty() { "block".to_owned() } else { slug };
+
+                        blocks.insert(name.to_string(), unique(slug, taken));
+                    }
+                }
+            }
+        }
+
+        Anchors {
+            sections,
+            blocks,
+        }
+    }
+
+    /// Only the anchors of the sections, which is all that other files link to
+    pub fn build_sections(file: &LinkedFile) -> Self {
+        Anchors {
+            sections: section_anchors(file, &mut HashSet::new()),
+            blocks: HashMap::new(),
+        }
+    }
+
+    pub fn section(&self, id: usize) -> &str {
+        // Every section gets an anchor when they are built
+        &self.sections[&id]
+    }
+
+    pub fn block(&self, name: &str) -> Option<&str> {
+        self.blocks.get(name).map(String::as_str)
+    }
+}
+
+fn section_anchors(file: &LinkedFile, taken: &mut HashSet<String>) -> HashMap<usize, String> {
+    file.sections.iter()
+        .map(|section| {
+            let slug = section.name.map(slugify).unwrap_or_default();
+            let slug = if slug.is_empty() { format!("section-{}", section.id) } else { slug };
+
+            (section.id, unique(slug, taken))
+        })
+        .collect()
+}
+
+/// Turn a name into something usable as an id, like `the-body` for "The body"
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+
+    for character in name.chars().flat_map(char::to_lowercase) {
+        if character.is_alphanumeric() || character == '_' {
+            slug.push(character);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+
+    slug
+}
+
+/// Add a number to the end of a slug if it has already been taken
+pub fn unique(slug: String, taken: &mut HashSet<String>) -> String {
+    if taken.insert(slug.clone()) {
+        return slug;
+    }
+
+    let mut suffix = 2_usize;
+    loop {
+        let candidate = format!("{}-{}", slug, suffix);
+        if taken.insert(candidate.clone()) {
+            return candidate;
+        }
+        suffix += 1;
+    }
+}
@@ -0,0 +1,289 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Books, whose chapters are woven into pages that link to each other.
+//!
+//! A `@book` page ends with an index of its chapters, and every chapter page has links to the
+//! chapters before and after it, and up to its book.
+
+use parser::{BookStatus};
+use link::{LinkedFile};
+use super::anchors::{Anchors};
+use super::toc::{SectionNumbers};
+
+use pulldown_cmark as cmark;
+
+use std::borrow::{Cow};
+use std::collections::{HashMap};
+use std::path::{Component, Path, PathBuf};
+
+/// A woven page
+#[derive(Clone)]
+pub struct Page<'b> {
+    pub title: &'b str,
+    /// Where the page is written in the output directory, without its extension
+    pub path: PathBuf,
+}
+
+pub struct Navigation<'b> {
+    pub up: Page<'b>,
+    pub previous: Option<Page<'b>>,
+    pub next: Option<Page<'b>>,
+}
+
+pub struct Chapter<'b> {
+    pub page: Page<'b>,
+    /// The number, name and anchor of each top-level section in the chapter
+    pub sections: Vec<(String, &'b str, String)>,
+}
+
+/// A file woven along with the rest of its book
+pub struct Part<'b> {
+    pub path: PathBuf,
+    pub file: &'b LinkedFile<'b>,
+    /// How many chapters down from the book the file is, from 0 for the book itself
+    pub depth: usize,
+}
+
+/// A book followed by its chapters in order, and their chapters, if they're books too
+pub fn parts<'b>(path: &Path, file_map: &'b HashMap<&'b PathBuf, LinkedFile<'b>>) -> Vec<Part<'b>> {
+    let mut parts = vec![];
+    add_parts(path, 0, file_map, &mut parts);
+    parts
+}
+
+fn add_parts<'b>(path: &Path, depth: usize, file_map: &'b HashMap<&'b PathBuf, LinkedFile<'b>>, parts: &mut Vec<Part<'b>>) {
+    let file = &file_map[&path.to_path_buf()];
+    parts.push(Part {
+        path: path.to_path_buf(),
+        file,
+        depth,
+    });
+
+    // Books can't include themselves, as the parser refuses include loops
+    if let BookStatus::IsBook(ref chapters) = file.book_status {
+        for chapter in chapters.iter() {
+            add_parts(chapter, depth + 1, file_map, parts);
+        }
+    }
+}
+
+pub struct Books<'b> {
+    navigation: HashMap<&'b Path, Navigation<'b>>,
+    contents: HashMap<&'b Path, Vec<Chapter<'b>>>,
+}
+
+impl<'b> Books<'b> {
+    pub fn build<'a : 'b>(file_map: &'b HashMap<&'a PathBuf, LinkedFile<'a>>) -> Self {
+        let mut navigation = HashMap::new();
+        let mut contents = HashMap::new();
+
+        for (path, file) in file_map.iter() {
+            let chapter_paths = match file.book_status {
+                BookStatus::IsBook(ref chapters) => chapters,
+                BookStatus::NotBook => continue,
+            };
+
+            // Every chapter was loaded with its book
+            let chapters : Vec<(&Path, &LinkedFile)> = chapter_paths.iter()
+                .map(|chapter| (chapter.as_path(), &file_map[chapter]))
+                .collect();
+            let pages : Vec<Page> = chapters.iter().map(|&(chapter, chapter_file)| page(chapter, chapter_file)).collect();
+
+            for (index, &(chapter, _)) in chapters.iter().enumerate() {
+                navigation.insert(chapter, Navigation {
+                    up: page(path, file),
+                    previous: index.checked_sub(1).map(|previous| pages[previous].clone()),
+                    next: pages.get(index + 1).cloned(),
+                });
+            }
+
+            let book_contents = chapters.iter().zip(pages).map(|(&(_, chapter_file), page)| {
+                let anchors = Anchors::build_sections(chapter_file);
+                let numbers = SectionNumbers::build(chapter_file);
+
+                let sections = chapter_file.sections.iter()
+                    .filter(|section| section.id != 0 && section.depth == 0)
+                    .filter_map(|section| section.name.map(|name| {
+                        (numbers.section(section.id).to_owned(), name, anchors.section(section.id).to_owned())
+                    }))
+                    .collect();
+
+                Chapter {
+                    page,
+                    sections,
+                }
+            }).collect();
+            contents.insert(path.as_path(), book_contents);
+        }
+
+        Books {
+            navigation,
+            contents,
+        }
+    }
+
+    /// Whether a file is a chapter of some book
+    pub fn is_chapter(&self, path: &Path) -> bool {
+        self.navigation.contains_key(path)
+    }
+
+    pub fn is_book(&self, path: &Path) -> bool {
+        self.contents.contains_key(path)
+    }
+
+    pub fn navigation(&self, path: &Path) -> Option<&Navigation<'b>> {
+        self.navigation.get(path)
+    }
+
+    pub fn contents(&self, path: &Path) -> Option<&[Chapter<'b>]> {
+        self.contents.get(path).map(|chapters| &chapters[..])
+    }
+}
+
+pub fn page<'b>(path: &Path, file: &'b LinkedFile) -> Page<'b> {
+    Page {
+        title: &file.title,
+        // Every input file has a name
+        path: file.relative_directory.join(path.file_stem().unwrap()),
+    }
+}
+
+/// A relative link from one page to another, like `../other.html#section`
+pub fn relative_link(from: &Path, to: &Path, extension: &str, anchor: Option<&str>) -> String {
+    let from : Vec<Component> = from.parent().map_or(vec![], |parent| parent.components().collect());
+    let to_components : Vec<Component> = to.components().collect();
+
+    let common = from.iter().zip(to_components.iter()).take_while(|(from, to)| from == to).count();
+
+    let mut parts : Vec<String> = from[common..].iter().map(|_| "..".to_owned()).collect();
+    parts.extend(to_components[common..].iter().map(|component| component.as_os_str().to_string_lossy().into_owned()));
+
+    let mut link = format!("{}.{}", parts.join("/"), extension);
+    if let Some(anchor) = anchor {
+        link.push('#');
+        link.push_str(anchor);
+    }
+    link
+}
+
+/// Links to the previous and next chapters, and up to the book, inside a `<nav>` which the
+/// Markdown weave leaves out
+pub fn build_navigation<'a>(navigation: &Navigation, from: &Path, extension: &str) -> Vec<cmark::Event<'a>> {
+    let mut events = vec![
+        cmark::Event::Html(Cow::Borrowed("<nav class=\"chapter-nav\">\n")),
+        cmark::Event::Start(cmark::Tag::Paragraph),
+    ];
+
+    let links = vec![
+        navigation.previous.as_ref().map(|page| ("← ", page, "")),
+        Some(("↑ ", &navigation.up, "")),
+        navigation.next.as_ref().map(|page| ("", page, " →")),
+    ];
+
+    for (index, (before, page, after)) in links.into_iter().flatten().enumerate() {
+        if index > 0 {
+            events.push(cmark::Event::Text(Cow::Borrowed(" | ")));
+        }
+
+        let link = cmark::Tag::Link(Cow::Owned(relative_link(from, &page.path, extension, None)), Cow::Borrowed(""));
+        events.push(cmark::Event::Start(link.clone()));
+        events.push(cmark::Event::Text(Cow::Owned(format!("{}{}{}", before, page.title, after))));
+        events.push(cmark::Event::End(link));
+    }
+
+    events.push(cmark::Event::End(cmark::Tag::Paragraph));
+    events.push(cmark::Event::Html(Cow::Borrowed("</nav>\n")));
+    events
+}
+
+/// A numbered list of the chapters of a book, with their top-level sections
+pub fn build_contents<'a>(chapters: &[Chapter], from: &Path, extension: &str) -> Vec<cmark::Event<'a>> {
+    let mut events = vec![
+        cmark::Event::Start(cmark::Tag::Header(2)),
+        cmark::Event::Text(Cow::Borrowed("Chapters")),
+        cmark::Event::End(cmark::Tag::Header(2)),
+        cmark::Event::Start(cmark::Tag::List(Some(1))),
+    ];
+
+    for chapter in chapters.iter() {
+        let link = cmark::Tag::Link(Cow::Owned(relative_link(from, &chapter.page.path, extension, None)), Cow::Borrowed(""));
+        events.push(cmark::Event::Start(cmark::Tag::Item));
+        events.push(cmark::Event::Start(cmark::Tag::Paragraph));
+        events.push(cmark::Event::Start(link.clone()));
+        events.push(cmark::Event::Text(Cow::Owned(chapter.page.title.to_owned())));
+        events.push(cmark::Event::End(link));
+        events.push(cmark::Event::End(cmark::Tag::Paragraph));
+
+        if !chapter.sections.is_empty() {
+            events.push(cmark::Event::Start(cmark::Tag::List(None)));
+            for (number, name, anchor) in chapter.sections.iter() {
+                let link = cmark::Tag::Link(Cow::Owned(relative_link(from, &chapter.page.path, extension, Some(anchor))), Cow::Borrowed(""));
+                events.push(cmark::Event::Start(cmark::Tag::Item));
+                events.push(cmark::Event::Start(cmark::Tag::Paragraph));
+                events.push(cmark::Event::Start(link.clone()));
+                events.push(cmark::Event::Text(Cow::Owned(format!("{} {}", number, name))));
+                events.push(cmark::Event::End(link));
+                events.push(cmark::Event::End(cmark::Tag::Paragraph));
+                events.push(cmark::Event::End(cmark::Tag::Item));
+            }
+            events.push(cmark::Event::End(cmark::Tag::List(None)));
+        }
+
+        events.push(cmark::Event::End(cmark::Tag::Item));
+    }
+
+    events.push(cmark::Event::End(cmark::Tag::List(Some(1))));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{relative_link};
+
+    use std::path::{Path};
+
+    fn link(from: &str, to: &str, anchor: Option<&str>) -> String {
+        relative_link(Path::new(from), Path::new(to), "html", anchor)
+    }
+
+    #[test]
+    fn links_in_the_same_directory() {
+        assert_eq!(link("book", "chapter", None), "chapter.html");
+        assert_eq!(link("parts/one", "parts/two", Some("intro")), "two.html#intro");
+    }
+
+    #[test]
+    fn links_down_into_a_directory() {
+        assert_eq!(link("book", "parts/deep/end", None), "parts/deep/end.html");
+    }
+
+    #[test]
+    fn links_up_out_of_a_directory() {
+        assert_eq!(link("parts/deep/end", "book", Some("chapters")), "../../book.html#chapters");
+    }
+
+    #[test]
+    fn links_across_directories() {
+        assert_eq!(link("parts/deep/end", "parts/other/start", None), "../other/start.html");
+        assert_eq!(link("a/page", "b/page", None), "../b/page.html");
+    }
+}
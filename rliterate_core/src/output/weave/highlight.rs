@@ -0,0 +1,517 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Syntax highlighting for code in the HTML weave.
+//!
+//! Code is split into tokens and wrapped in `<span>`s, using the class names of Google's
+//! prettify - `kwd` for keywords, `typ` for types, `str` for strings, `com` for comments and `lit`
+//! for numbers - so that the colorschemes written for `literate` still work. Nothing is run in
+//! the browser.
+//!
+//! This is a simple lexer, which only knows the keywords, comments and strings of each language.
+
+static DEFAULT_COLORSCHEME : &str = include_str!("colorscheme.css");
+
+pub fn default_colorscheme() -> &'static str {
+    DEFAULT_COLORSCHEME
+}
+
+pub struct Language {
+    /// The `@code_type`s and file extensions that this language is picked for
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    /// Treat identifiers starting with a capital letter as types
+    capitalised_types: bool,
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// `'` only starts a string when it holds a single character, as it is also used for Rust's
+    /// lifetimes
+    char_literals: bool,
+}
+
+const C_KEYWORDS : &[&str] = &[
+    "auto", "break", "case", "const", "continue", "default", "do", "else", "enum", "extern",
+    "for", "goto", "if", "inline", "register", "restrict", "return", "sizeof", "static", "struct",
+    "switch", "typedef", "union", "volatile", "while", "#include", "#define", "#if", "#ifdef",
+    "#ifndef", "#else", "#elif", "#endif", "#pragma",
+];
+
+const C_TYPES : &[&str] = &[
+    "bool", "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void",
+    "size_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t",
+    "uint64_t",
+];
+
+static LANGUAGES : &[Language] = &[
+    Language {
+        names: &["rust", "rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+            "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+            "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        types: &[
+            "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16",
+            "i32", "i64", "i128", "isize", "f32", "f64",
+        ],
+        capitalised_types: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+        char_literals: true,
+    },
+    Language {
+        names: &["c", "h"],
+        keywords: C_KEYWORDS,
+        types: C_TYPES,
+        capitalised_types: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+    Language {
+        names: &["cpp", "c++", "cc", "cxx", "hpp", "hh"],
+        keywords: &[
+            "auto", "break", "case", "catch", "class", "const", "constexpr", "continue",
+            "default", "delete", "do", "else", "enum", "explicit", "extern", "false", "for",
+            "friend", "goto", "if", "inline", "mutable", "namespace", "new", "noexcept",
+            "nullptr", "operator", "private", "protected", "public", "return", "sizeof",
+            "static", "struct", "switch", "template", "this", "throw", "true", "try", "typedef",
+            "typename", "union", "using", "virtual", "volatile", "while", "#include", "#define",
+            "#if", "#ifdef", "#ifndef", "#else", "#elif", "#endif", "#pragma",
+        ],
+        types: C_TYPES,
+        capitalised_types: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+    Language {
+        names: &["d"],
+        keywords: &[
+            "abstract", "alias", "assert", "auto", "break", "case", "cast", "catch", "class",
+            "const", "continue", "default", "delegate", "do", "else", "enum", "false", "final",
+            "finally", "for", "foreach", "function", "if", "immutable", "import", "in", "interface",
+            "is", "module", "new", "null", "out", "override", "private", "protected", "public",
+            "pure", "ref", "return", "scope", "shared", "static", "struct", "super", "switch",
+            "template", "this", "throw", "true", "try", "typeof", "union", "while",
+        ],
+        types: &[
+            "bool", "byte", "ubyte", "short", "ushort", "int", "uint", "long", "ulong", "float",
+            "double", "real", "char", "wchar", "dchar", "string", "void", "size_t",
+        ],
+        capitalised_types: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        char_literals: false,
+    },
+    Language {
+        names: &["go"],
+        keywords: &[
+            "break", "case", "chan", "const", "continue", "default", "defer", "else",
+            "fallthrough", "false", "for", "func", "go", "goto", "if", "import", "interface",
+            "map", "nil", "package", "range", "return", "select", "struct", "switch", "true",
+            "type", "var",
+        ],
+        types: &[
+            "bool", "byte", "complex64", "complex128", "error", "float32", "float64", "int",
+            "int8", "int16", "int32", "int64", "rune", "string", "uint", "uint8", "uint16",
+            "uint32", "uint64", "uintptr",
+        ],
+        capitalised_types: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        char_literals: false,
+    },
+    Language {
+        names: &["java"],
+        keywords: &[
+            "abstract", "assert", "break", "case", "catch", "class", "const", "continue",
+            "default", "do", "else", "enum", "extends", "false", "final", "finally", "for", "if",
+            "implements", "import", "instanceof", "interface", "native", "new", "null",
+            "package", "private", "protected", "public", "return", "static", "super", "switch",
+            "synchronized", "this", "throw", "throws", "true", "try", "var", "volatile", "while",
+        ],
+        types: &["boolean", "byte", "char", "double", "float", "int", "long", "short", "void"],
+        capitalised_types: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+    Language {
+        names: &["javascript", "js", "typescript", "ts"],
+        keywords: &[
+            "async", "await", "break", "case", "catch", "class", "const", "continue", "default",
+            "delete", "do", "else", "export", "extends", "false", "finally", "for", "from",
+            "function", "if", "import", "in", "instanceof", "interface", "let", "new", "null",
+            "of", "return", "super", "switch", "this", "throw", "true", "try", "type", "typeof",
+            "undefined", "var", "void", "while", "yield",
+        ],
+        types: &["any", "boolean", "number", "string", "unknown", "never"],
+        capitalised_types: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        char_literals: false,
+    },
+    Language {
+        names: &["python", "py"],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+            "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+            "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return",
+            "True", "try", "while", "with", "yield",
+        ],
+        types: &["bool", "bytes", "dict", "float", "int", "list", "object", "set", "str", "tuple"],
+        capitalised_types: false,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+    Language {
+        names: &["shell", "sh", "bash", "zsh"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "until", "while",
+        ],
+        types: &[],
+        capitalised_types: false,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+    Language {
+        names: &["toml"],
+        keywords: &["true", "false"],
+        types: &[],
+        capitalised_types: false,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        char_literals: false,
+    },
+];
+
+impl Language {
+    pub fn name(&self) -> &'static str {
+        self.names[0]
+    }
+}
+
+/// Find the language for a `@code_type` or file extension, like `rust` or `rs`
+pub fn language(name: &str) -> Option<&'static Language> {
+    let name = name.trim_start_matches('.').to_lowercase();
+    LANGUAGES.iter().find(|language| language.names.contains(&name.as_str()))
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Code,
+    LineComment,
+    BlockComment(&'static str),
+    Str(char),
+}
+
+/// Highlights a block of code a piece at a time, so that block references can be written between
+/// the pieces
+pub struct Highlighter {
+    language: Option<&'static Language>,
+    state: State,
+}
+
+impl Highlighter {
+    pub fn new(language: Option<&'static Language>) -> Self {
+        Highlighter {
+            language,
+            state: State::Code,
+        }
+    }
+
+    /// Write a piece of a line as HTML
+    pub fn push(&mut self, html: &mut String, text: &str) {
+        let language = match self.language {
+            Some(language) => language,
+            None => return escape_html(html, text),
+        };
+
+        let mut rest = text;
+        while !rest.is_empty() {
+            let (class, length) = match self.state {
+                State::LineComment => ("com", rest.len()),
+                State::BlockComment(end) => match rest.find(end) {
+                    Some(index) => {
+                        self.state = State::Code;
+                        ("com", index + end.len())
+                    },
+                    None => ("com", rest.len()),
+                },
+                State::Str(quote) => match find_closing_quote(rest, quote) {
+                    Some(index) => {
+                        self.state = State::Code;
+                        ("str", index + quote.len_utf8())
+                    },
+                    None => ("str", rest.len()),
+                },
+                State::Code => self.next_token(language, text, rest),
+            };
+
+            let (token, remaining) = rest.split_at(length);
+            write_token(html, class, token);
+            rest = remaining;
+        }
+    }
+
+    /// Line comments end with the line
+    pub fn end_line(&mut self) {
+        if let State::LineComment = self.state {
+            self.state = State::Code;
+        }
+    }
+
+    // Find the class and length of the token at the start of `rest`, which is part of `text`
+    fn next_token(&mut self, language: &Language, text: &str, rest: &str) -> (&'static str, usize) {
+        let preceding = text[..text.len() - rest.len()].chars().next_back();
+        let first = match rest.chars().next() {
+            Some(character) => character,
+            None => return ("", 0),
+        };
+
+        // `#` is also used inside words and shell variables, so only starts a comment on its own
+        let starts_comment = |comment: &&str| {
+            rest.starts_with(*comment) && (*comment != "#" || preceding.is_none_or(char::is_whitespace))
+        };
+        if language.line_comments.iter().any(starts_comment) {
+            // The comment is written on the next pass, now that the state has changed
+            self.state = State::LineComment;
+            return ("", 0);
+        }
+
+        if let Some((start, end)) = language.block_comment {
+            if let Some(comment) = rest.strip_prefix(start) {
+                self.state = State::BlockComment(end);
+                return match comment.find(end) {
+                    Some(index) => {
+                        self.state = State::Code;
+                        ("com", start.len() + index + end.len())
+                    },
+                    None => ("com", rest.len()),
+                };
+            }
+        }
+
+        if language.quotes.contains(&first) {
+            return match find_closing_quote(&rest[1..], first) {
+                Some(index) => ("str", 1 + index + 1),
+                None => {
+                    self.state = State::Str(first);
+                    ("str", rest.len())
+                },
+            };
+        }
+
+        if first == '\'' && language.char_literals {
+            if let Some(length) = char_literal(rest) {
+                return ("str", length);
+            }
+        }
+
+        let follows_word = preceding.is_some_and(is_identifier_char);
+
+        if first.is_ascii_digit() && !follows_word {
+            let length = number(rest);
+            return ("lit", length);
+        }
+
+        if (is_identifier_char(first) || first == '#') && !follows_word {
+            let length = rest[1..].find(|character: char| !is_identifier_char(character)).map_or(rest.len(), |index| index + 1);
+            let word = &rest[..length];
+
+            let class = if language.keywords.contains(&word) {
+                "kwd"
+            } else if language.types.contains(&word) || (language.capitalised_types && first.is_uppercase()) {
+                "typ"
+            } else {
+                ""
+            };
+            return (class, length);
+        }
+
+        ("", first.len_utf8())
+    }
+}
+
+fn is_identifier_char(character: char) -> bool {
+    character.is_alphanumeric() || character == '_'
+}
+
+// The index of the quote which ends a string, skipping escaped characters
+fn find_closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, character) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if character == '\\' {
+            escaped = true;
+        } else if character == quote {
+            return Some(index);
+        }
+    }
+    None
+}
+
+// A character literal like `'a'` or `'\n'`, rather than a lifetime like `'a`
+fn char_literal(text: &str) -> Option<usize> {
+    let inner = text.strip_prefix('\'')?;
+
+    if let Some(escape) = inner.strip_prefix('\\') {
+        // Skip the escaped character, which might itself be a quote
+        let escaped = escape.chars().next()?.len_utf8();
+        let index = escape[escaped..].find('\'')?;
+        return Some(2 + escaped + index + 1);
+    }
+
+    let character = inner.chars().next().filter(|&character| character != '\'')?;
+    let end = 1 + character.len_utf8();
+    if text[end..].starts_with('\'') { Some(end + 1) } else { None }
+}
+
+// Numbers, including suffixes and decimal points, like `1_000u32` or `2.5`
+fn number(text: &str) -> usize {
+    let mut length = 0;
+    let mut characters = text.char_indices().peekable();
+    while let Some((index, character)) = characters.next() {
+        let is_decimal_point = character == '.' && characters.peek().is_some_and(|&(_, next)| next.is_ascii_digit());
+        if !(is_identifier_char(character) || is_decimal_point) {
+            break;
+        }
+        length = index + character.len_utf8();
+    }
+    length
+}
+
+fn write_token(html: &mut String, class: &str, token: &str) {
+    if token.is_empty() {
+        return;
+    }
+
+    if class.is_empty() {
+        escape_html(html, token);
+    } else {
+        html.push_str("<span class=\"");
+        html.push_str(class);
+        html.push_str("\">");
+        escape_html(html, token);
+        html.push_str("</span>");
+    }
+}
+
+pub fn escape_html(html: &mut String, text: &str) {
+    for character in text.chars() {
+        match character {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            _ => html.push(character),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Highlighter, language};
+
+    /// Highlight some lines of code, as the weave does
+    fn highlight(language_name: &str, lines: &[&str]) -> String {
+        let mut html = String::new();
+        let mut highlighter = Highlighter::new(language(language_name));
+        for line in lines.iter() {
+            highlighter.push(&mut html, line);
+            highlighter.end_line();
+            html.push('\n');
+        }
+        html
+    }
+
+    #[test]
+    fn keywords_strings_and_comments_are_spans() {
+        assert_eq!(highlight("rust", &["let s: &str = \"hi\"; // greet"]),
+                   "<span class=\"kwd\">let</span> s: &amp;<span class=\"typ\">str</span> = <span class=\"str\">&quot;hi&quot;</span>; <span class=\"com\">// greet</span>\n");
+    }
+
+    #[test]
+    fn types_and_numbers_are_spans() {
+        assert_eq!(highlight("rust", &["let v: Vec<u8> = vec![1_000];"]),
+                   "<span class=\"kwd\">let</span> v: <span class=\"typ\">Vec</span>&lt;<span class=\"typ\">u8</span>&gt; = vec![<span class=\"lit\">1_000</span>];\n");
+    }
+
+    #[test]
+    fn block_comments_span_lines() {
+        assert_eq!(highlight("c", &["int x; /* one", "two */ int y;"]),
+                   "<span class=\"typ\">int</span> x; <span class=\"com\">/* one</span>\n\
+                    <span class=\"com\">two */</span> <span class=\"typ\">int</span> y;\n");
+    }
+
+    #[test]
+    fn strings_span_lines() {
+        assert_eq!(highlight("rust", &["let s = \"one", "two\"; let t = 1;"]),
+                   "<span class=\"kwd\">let</span> s = <span class=\"str\">&quot;one</span>\n\
+                    <span class=\"str\">two&quot;</span>; <span class=\"kwd\">let</span> t = <span class=\"lit\">1</span>;\n");
+    }
+
+    #[test]
+    fn line_comments_end_with_the_line() {
+        assert_eq!(highlight("python", &["# if", "if x"]),
+                   "<span class=\"com\"># if</span>\n<span class=\"kwd\">if</span> x\n");
+    }
+
+    #[test]
+    fn links_in_comments_keep_the_comment_going() {
+        let mut html = String::new();
+        let mut highlighter = Highlighter::new(language("rust"));
+        highlighter.push(&mut html, "// see ");
+        html.push_str("<a href=\"#body\">{body}</a>");
+        highlighter.push(&mut html, " for let");
+        highlighter.end_line();
+
+        assert_eq!(html, "<span class=\"com\">// see </span><a href=\"#body\">{body}</a><span class=\"com\"> for let</span>");
+    }
+
+    #[test]
+    fn code_is_escaped() {
+        assert_eq!(highlight("rust", &["if a < b && c > d {}"]),
+                   "<span class=\"kwd\">if</span> a &lt; b &amp;&amp; c &gt; d {}\n");
+        assert_eq!(highlight("rust", &["\"<&>\" // <&>"]),
+                   "<span class=\"str\">&quot;&lt;&amp;&gt;&quot;</span> <span class=\"com\">// &lt;&amp;&gt;</span>\n");
+        // Code in a language that isn't known is escaped too
+        assert_eq!(highlight("cobol", &["A < B & C"]), "A &lt; B &amp; C\n");
+    }
+}
@@ -21,6 +21,7 @@
 
 use output;
 use output::css;
+use super::highlight;
 
 use maud::html;
 use maud::{DOCTYPE, PreEscaped};
@@ -30,13 +31,17 @@ use std::io::{Write};
 
 static DEFAULT_CSS_STYLE : &'static str = include_str!("default.css");
 static KATEX_HTML : &'static str = include_str!("katex.html");
+static TOC_CSS_STYLE : &str = include_str!("toc.css");
 
-pub fn print(mut file: fs::File, html: String, title: &str, css: &css::Globals) -> output::Result<()> {
+pub fn print<W: Write>(file: &mut W, html: String, title: &str, css: &css::Globals) -> output::Result<()> {
     let markup = html! {
         (DOCTYPE);
         head {
             title { (title) };
             (PreEscaped(KATEX_HTML))
+            style {
+                (PreEscaped(TOC_CSS_STYLE))
+            }
             (match css.custom_css {
                 css::CustomCss::None => html! {},
                 css::CustomCss::Add(ref file_path) => {
@@ -62,7 +67,11 @@ pub fn print(mut file: fs::File, html: String, title: &str, css: &css::Globals)
                     }
                 }
             } else {
-                html! {}
+                html! {
+                    style {
+                        (PreEscaped(highlight::default_colorscheme()))
+                    }
+                }
             })
         };
         body {
@@ -0,0 +1,610 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! The LaTeX weave, which writes a `.tex` document straight from the linked file rather than going
+//! through Markdown, so that blocks can be captioned and cross-referenced the LaTeX way.
+
+use super::{CodeEnvironment};
+use super::anchors::{Anchors};
+use super::book::{Chapter, Part};
+use super::highlight::{Language};
+use super::markdown::{ProseDocument, block_language, find_uses, is_woven};
+use output::canon::{BlockMap, CanonicalCodeBlock, FragmentKind};
+use parser::{BlockModifier, TableOfContents};
+use link::{LinkedFile, LinkedBlock, LinkedLine};
+
+use pulldown_cmark as cmark;
+
+use std::collections::{HashMap, HashSet};
+
+/// The sectioning commands for each depth of section - anything deeper uses the last one
+const SECTIONING : &[&str] = &["section", "subsection", "subsubsection", "paragraph", "subparagraph"];
+
+/// The first character standing in for a piece of math while prose is parsed as Markdown, from
+/// the Unicode private use area
+const MATH_PLACEHOLDER : u32 = 0xE000;
+const MAX_MATHS : usize = 0x1900;
+
+pub fn build(settings: &super::Globals, environment: CodeEnvironment, file: &LinkedFile, block_map: &BlockMap, chapters: Option<&[Chapter]>) -> String {
+    let mut latex = String::new();
+
+    build_preamble(&mut latex, environment, &file.title);
+    build_toc(&mut latex, file.toc.or(settings.toc).unwrap_or(TableOfContents::Top));
+
+    let anchors = Anchors::build(file, block_map);
+    build_body(&mut latex, environment, file, block_map, &anchors);
+
+    // The chapters are separate documents, so they are listed without links or navigation
+    if let Some(chapters) = chapters {
+        build_contents(&mut latex, chapters);
+    }
+
+    latex.push_str("\\end{document}\n");
+    latex
+}
+
+/// A book and all of its chapters, as one document with a part for each chapter
+pub fn build_book(settings: &super::Globals, environment: CodeEnvironment, parts: &[Part], block_maps: &[BlockMap]) -> String {
+    let mut latex = String::new();
+    // The book itself is always the first part
+    let book = parts[0].file;
+
+    build_preamble(&mut latex, environment, &book.title);
+    build_toc(&mut latex, book.toc.or(settings.toc).unwrap_or(TableOfContents::Top));
+
+    // LaTeX numbers the sections on from one part to the next by itself, but the labels of every
+    // part share one namespace
+    let mut taken = HashSet::new();
+
+    for (part, block_map) in parts.iter().zip(block_maps.iter()) {
+        if part.depth > 0 {
+            latex.push_str("\\part{");
+            escape_latex(&mut latex, &part.file.title);
+            latex.push_str("}\n\n");
+        }
+
+        let anchors = Anchors::build_with(part.file, block_map, &mut taken);
+        build_body(&mut latex, environment, part.file, block_map, &anchors);
+    }
+
+    latex.push_str("\\end{document}\n");
+    latex
+}
+
+fn build_preamble(latex: &mut String, environment: CodeEnvironment, title: &str) {
+    latex.push_str("\\documentclass{article}\n\
+                    \\usepackage[utf8]{inputenc}\n\
+                    \\usepackage[T1]{fontenc}\n\
+                    \\usepackage{amsmath}\n\
+                    \\usepackage{amssymb}\n\
+                    \\usepackage{graphicx}\n");
+
+    match environment {
+        CodeEnvironment::Listings => latex.push_str("\\usepackage{listings}\n\
+                                                     \\lstset{basicstyle=\\ttfamily\\small, columns=fullflexible, breaklines=true, frame=single}\n"),
+        CodeEnvironment::Minted => latex.push_str("\\usepackage{float}\n\
+                                                   \\usepackage{minted}\n\
+                                                   \\setminted{fontsize=\\small, breaklines, frame=single}\n"),
+    }
+
+    // Sections are numbered however deep they go, as in the other weaves
+    latex.push_str("\\usepackage{hyperref}\n\
+                    \\setcounter{secnumdepth}{5}\n\n\
+                    \\title{");
+    escape_latex(latex, title);
+    latex.push_str("}\n\
+                    \\date{}\n\n\
+                    \\begin{document}\n\n\
+                    \\maketitle\n\n");
+}
+
+// There are no sidebars on paper, so a sidebar is just a table of contents at the top
+fn build_toc(latex: &mut String, placement: TableOfContents) {
+    match placement {
+        TableOfContents::None => {},
+        TableOfContents::Top | TableOfContents::Sidebar => latex.push_str("\\tableofcontents\n\n"),
+    }
+}
+
+fn build_contents(latex: &mut String, chapters: &[Chapter]) {
+    latex.push_str("\\section*{Chapters}\n\n\\begin{enumerate}\n");
+    for chapter in chapters.iter() {
+        latex.push_str("\\item ");
+        escape_latex(latex, chapter.page.title);
+        latex.push('\n');
+    }
+    latex.push_str("\\end{enumerate}\n\n");
+}
+
+// The sections of a file, with their prose and code
+fn build_body(latex: &mut String, environment: CodeEnvironment, file: &LinkedFile, block_map: &BlockMap, anchors: &Anchors) {
+    let used_in = find_uses(file);
+
+    let mut defined_blocks = HashSet::new();
+    let mut anchored_blocks = HashSet::new();
+
+    for section in file.sections.iter() {
+        if section.name.is_some() || !section.blocks.is_empty() {
+            build_section_header(latex, section.id, section.name, section.depth, anchors.section(section.id));
+        }
+
+        for block in section.blocks.iter() {
+            match block {
+                LinkedBlock::Code { name, modifiers, lines } => {
+                    let modifiers = *modifiers;
+                    let kind = if modifiers.contains(BlockModifier::REDEF) {
+                        FragmentKind::Redefinition
+                    } else if defined_blocks.insert(name.as_str()) {
+                        FragmentKind::Definition
+                    } else {
+                        FragmentKind::Append
+                    };
+
+                    if !is_woven(name, modifiers, block_map) {
+                        trace!("Leaving the block \"{}\" out of the weave", name);
+                        continue;
+                    }
+
+                    // The first piece of a block shown in the weave is where references to it go
+                    let anchor = if anchored_blocks.insert(name.as_str()) {
+                        anchors.block(name)
+                    } else {
+                        None
+                    };
+
+                    let canonical = block_map.get(name.as_str());
+                    let caption = build_caption(name, kind, modifiers, canonical);
+
+                    build_code_block(latex, environment, lines, caption, anchor, block_language(name, canonical, &file.code_type));
+                    if let Some(block) = canonical {
+                        build_cross_references(latex, name, block, &used_in, anchors);
+                    }
+                },
+                LinkedBlock::Prose { lines } => {
+                    build_prose(latex, &ProseDocument::build(lines, anchors));
+                },
+            }
+        }
+    }
+}
+
+fn build_section_header(latex: &mut String, id: usize, name: Option<&str>, depth: usize, anchor: &str) {
+    // The implicit section before the first `@s` has no heading or number to refer to
+    if id == 0 {
+        latex.push_str(&format!("\\phantomsection\\label{{{}}}\n\n", anchor));
+        return;
+    }
+
+    let command = SECTIONING[depth.min(SECTIONING.len() - 1)];
+
+    if let Some(name) = name {
+        latex.push_str(&format!("\\{}{{", command));
+        escape_latex(latex, name);
+        latex.push_str(&format!("}}\\label{{{}}}\n\n", anchor));
+    } else {
+        // Sections without a name still take a number, so that the ones after them match the
+        // numbers in the other weaves
+        latex.push_str(&format!("\\refstepcounter{{{}}}\\label{{{}}}\n\n", command, anchor));
+    }
+}
+
+// Like `{Block name} +=` - file blocks are shown as code, without the braces
+fn build_caption(name: &str, kind: FragmentKind, modifiers: BlockModifier, canonical: Option<&CanonicalCodeBlock>) -> Option<String> {
+    if modifiers.contains(BlockModifier::NOHEADER) || canonical.is_some_and(|block| !block.print_header()) {
+        return None;
+    }
+
+    let mut caption = String::new();
+    if canonical.is_some_and(|block| block.is_file()) {
+        caption.push_str("\\texttt{");
+        escape_latex(&mut caption, name);
+        caption.push('}');
+    } else {
+        caption.push_str("\\{");
+        escape_latex(&mut caption, name);
+        caption.push_str("\\}");
+    }
+
+    match kind {
+        FragmentKind::Definition => {},
+        FragmentKind::Append => caption.push_str(" +="),
+        FragmentKind::Redefinition => caption.push_str(" -="),
+    }
+
+    Some(caption)
+}
+
+fn build_code_block(latex: &mut String, environment: CodeEnvironment, lines: &[LinkedLine], caption: Option<String>, anchor: Option<&str>, language: Option<&'static Language>) {
+    if let Some(anchor) = anchor {
+        latex.push_str(&format!("\\phantomsection\\label{{{}}}\n", anchor));
+    }
+
+    match environment {
+        CodeEnvironment::Listings => {
+            let mut options = vec![];
+            if let Some(language) = language.and_then(listings_language) {
+                options.push(format!("language={}", language));
+            }
+            if let Some(caption) = caption {
+                options.push(format!("caption={{{}}}", caption));
+            }
+
+            latex.push_str("\\begin{lstlisting}");
+            if !options.is_empty() {
+                latex.push_str(&format!("[{}]", options.join(", ")));
+            }
+            latex.push('\n');
+            build_code_lines(latex, lines);
+            latex.push_str("\\end{lstlisting}\n\n");
+        },
+        CodeEnvironment::Minted => {
+            // A caption needs a float around the code, which is kept in place with `[H]`
+            if let Some(ref caption) = caption {
+                latex.push_str(&format!("\\begin{{listing}}[H]\n\\caption{{{}}}\n", caption));
+            }
+
+            latex.push_str(&format!("\\begin{{minted}}{{{}}}\n", language.map_or("text", minted_language)));
+            build_code_lines(latex, lines);
+            latex.push_str("\\end{minted}\n");
+
+            if caption.is_some() {
+                latex.push_str("\\end{listing}\n");
+            }
+            latex.push('\n');
+        },
+    }
+}
+
+fn build_code_lines(latex: &mut String, lines: &[LinkedLine]) {
+    for line in lines.iter() {
+        latex.push_str(line.get_text());
+        latex.push('\n');
+    }
+}
+
+// Only some of the languages that can be highlighted are known to `listings`, which rejects any
+// language it doesn't know
+fn listings_language(language: &Language) -> Option<&'static str> {
+    match language.name() {
+        "c" => Some("C"),
+        "cpp" => Some("C++"),
+        "java" => Some("Java"),
+        "python" => Some("Python"),
+        "shell" => Some("bash"),
+        _ => None,
+    }
+}
+
+// The Pygments lexer for a language
+fn minted_language(language: &Language) -> &'static str {
+    match language.name() {
+        "shell" => "bash",
+        name => name,
+    }
+}
+
+// Footers like "Used in §2, §3.1.", with the numbers filled in by `\ref`
+fn build_cross_references(latex: &mut String, name: &str, block: &CanonicalCodeBlock, used_in: &HashMap<&str, Vec<usize>>, anchors: &Anchors) {
+    let footers : Vec<(&str, &[usize])> = vec![
+        ("Used in", used_in.get(name).map_or(&[], |sections| &sections[..])),
+        ("Added to in", block.appended_to_in()),
+        ("Redefined in", block.redefined_in()),
+    ];
+
+    let footers : Vec<String> = footers.into_iter()
+        .filter(|(_, sections)| !sections.is_empty())
+        .map(|(label, sections)| {
+            let references : Vec<String> = sections.iter()
+                .map(|&section| if section == 0 {
+                    format!("\\hyperref[{}]{{\\S0}}", anchors.section(section))
+                } else {
+                    format!("\\S\\ref{{{}}}", anchors.section(section))
+                })
+                .collect();
+
+            format!("\\emph{{{} {}.}}", label, references.join(", "))
+        })
+        .collect();
+
+    if !footers.is_empty() {
+        latex.push_str(&footers.join("\\\\\n"));
+        latex.push_str("\n\n");
+    }
+}
+
+// Prose is Markdown, which is parsed and then written out as LaTeX. Math is kept out of the
+// Markdown parser's way, so that it reaches the document untouched.
+fn build_prose(latex: &mut String, prose: &ProseDocument) {
+    let protected = ProtectedMath::protect(&prose.markdown);
+
+    let mut in_code_block = false;
+    let mut in_image = false;
+    let mut first_cell = false;
+
+    let mut parser = cmark::Parser::new_ext(&protected.text, cmark::OPTION_ENABLE_TABLES);
+    while let Some(event) = parser.next() {
+        match event {
+            cmark::Event::Start(tag) => match tag {
+                cmark::Tag::Paragraph => {},
+                cmark::Tag::Rule => latex.push_str("\\noindent\\rule{\\linewidth}{0.4pt}\n\n"),
+                cmark::Tag::Header(_) => latex.push_str("\\paragraph*{"),
+                cmark::Tag::BlockQuote => latex.push_str("\\begin{quote}\n"),
+                cmark::Tag::CodeBlock(_) => {
+                    in_code_block = true;
+                    latex.push_str("\\begin{verbatim}\n");
+                },
+                cmark::Tag::List(None) => latex.push_str("\\begin{itemize}\n"),
+                cmark::Tag::List(Some(_)) => latex.push_str("\\begin{enumerate}\n"),
+                cmark::Tag::Item => latex.push_str("\\item "),
+                cmark::Tag::FootnoteDefinition(_) => {},
+                cmark::Tag::Table(alignments) => {
+                    let columns : String = alignments.iter().map(|alignment| match alignment {
+                        cmark::Alignment::Center => 'c',
+                        cmark::Alignment::Right => 'r',
+                        cmark::Alignment::None | cmark::Alignment::Left => 'l',
+                    }).collect();
+                    latex.push_str(&format!("\\begin{{tabular}}{{{}}}\n", columns));
+                },
+                cmark::Tag::TableHead | cmark::Tag::TableRow => first_cell = true,
+                cmark::Tag::TableCell => {
+                    if !first_cell {
+                        latex.push_str(" & ");
+                    }
+                    first_cell = false;
+                },
+                cmark::Tag::Emphasis => latex.push_str("\\emph{"),
+                cmark::Tag::Strong => latex.push_str("\\textbf{"),
+                cmark::Tag::Code => latex.push_str("\\texttt{"),
+                cmark::Tag::Link(url, _) => {
+                    // Links within the document are to the labels of sections and blocks
+                    if let Some(anchor) = url.strip_prefix('#') {
+                        latex.push_str(&format!("\\hyperref[{}]{{", anchor));
+                    } else {
+                        latex.push_str("\\href{");
+                        escape_url(latex, &url);
+                        latex.push_str("}{");
+                    }
+                },
+                cmark::Tag::Image(url, _) => {
+                    // There's nowhere for the alt text to go
+                    in_image = true;
+                    latex.push_str("\\includegraphics{");
+                    escape_url(latex, &url);
+                    latex.push('}');
+                },
+            },
+            cmark::Event::End(tag) => match tag {
+                cmark::Tag::Paragraph => latex.push_str("\n\n"),
+                cmark::Tag::Rule | cmark::Tag::FootnoteDefinition(_) | cmark::Tag::TableCell => {},
+                cmark::Tag::Header(_) => latex.push_str("}\n\n"),
+                cmark::Tag::BlockQuote => latex.push_str("\\end{quote}\n\n"),
+                cmark::Tag::CodeBlock(_) => {
+                    in_code_block = false;
+                    latex.push_str("\\end{verbatim}\n\n");
+                },
+                cmark::Tag::List(None) => latex.push_str("\\end{itemize}\n\n"),
+                cmark::Tag::List(Some(_)) => latex.push_str("\\end{enumerate}\n\n"),
+                cmark::Tag::Item => latex.push('\n'),
+                cmark::Tag::Table(_) => latex.push_str("\\end{tabular}\n\n"),
+                cmark::Tag::TableHead => latex.push_str(" \\\\\n\\hline\n"),
+                cmark::Tag::TableRow => latex.push_str(" \\\\\n"),
+                cmark::Tag::Emphasis | cmark::Tag::Strong | cmark::Tag::Code | cmark::Tag::Link(..) => latex.push('}'),
+                cmark::Tag::Image(..) => in_image = false,
+            },
+            cmark::Event::Text(text) => {
+                if in_image {
+                    continue;
+                }
+
+                for character in text.chars() {
+                    match protected.math(character) {
+                        // Math that was only mistaken for math, in an indented code block, goes back as it was
+                        Some(math) if in_code_block => latex.push_str(math),
+                        Some(math) => latex.push_str(math),
+                        None if in_code_block => latex.push(character),
+                        None => escape_latex(latex, character.encode_utf8(&mut [0; 4])),
+                    }
+                }
+            },
+            cmark::Event::Html(_) | cmark::Event::InlineHtml(_) => {
+                // The parser is just past the HTML, so the line it's on is the one before
+                let offset = protected.original_offset(parser.get_offset().saturating_sub(1));
+                debug!("Leaving the HTML on line {} out of the LaTeX weave", prose.line_number(offset));
+            },
+            cmark::Event::FootnoteReference(_) => {},
+            cmark::Event::SoftBreak => latex.push('\n'),
+            cmark::Event::HardBreak => latex.push_str("\\\\\n"),
+        }
+    }
+}
+
+/// Markdown with each piece of math replaced by a single character, which the Markdown parser
+/// leaves alone
+struct ProtectedMath<'m> {
+    text: String,
+    /// The math that each character stands for
+    maths: Vec<&'m str>,
+    /// Where each piece of math ends, in the text and in the original Markdown
+    ends: Vec<(usize, usize)>,
+}
+
+impl<'m> ProtectedMath<'m> {
+    fn protect(markdown: &'m str) -> Self {
+        let mut text = String::with_capacity(markdown.len());
+        let mut maths = vec![];
+        let mut ends = vec![];
+        let mut rest = markdown;
+
+        while let Some(start) = rest.find(['$', '\\', '`']) {
+            let (before, from) = rest.split_at(start);
+            text.push_str(before);
+
+            // Dollars in code aren't math
+            if from.starts_with('`') {
+                let length = code_length(from);
+                text.push_str(&from[..length]);
+                rest = &from[length..];
+                continue;
+            }
+
+            match math_length(from) {
+                Some(length) if maths.len() < MAX_MATHS => {
+                    // Only as many placeholders as there are maths are made, so this is a valid char
+                    text.push(std::char::from_u32(MATH_PLACEHOLDER + maths.len() as u32).unwrap());
+                    maths.push(&from[..length]);
+                    rest = &from[length..];
+                    ends.push((text.len(), markdown.len() - rest.len()));
+                },
+                _ => {
+                    // A backslash escapes the character after it, like an escaped `\$`
+                    let skipped = match from.strip_prefix('\\') {
+                        Some(escaped) => 1 + escaped.chars().next().map_or(0, char::len_utf8),
+                        None => 1,
+                    };
+                    text.push_str(&from[..skipped]);
+                    rest = &from[skipped..];
+                },
+            }
+        }
+        text.push_str(rest);
+
+        ProtectedMath {
+            text,
+            maths,
+            ends,
+        }
+    }
+
+    // The math that a character stands for, if it's a placeholder
+    fn math(&self, character: char) -> Option<&'m str> {
+        (character as u32).checked_sub(MATH_PLACEHOLDER)
+            .and_then(|index| self.maths.get(index as usize))
+            .cloned()
+    }
+
+    // The offset in the original Markdown of an offset into the text
+    fn original_offset(&self, offset: usize) -> usize {
+        self.ends.iter()
+            .take_while(|&&(end, _)| end <= offset)
+            .last()
+            .map_or(offset, |&(end, original_end)| original_end + (offset - end))
+    }
+}
+
+// The length of the math at the start of some text, including its delimiters, if it starts with
+// any - `$...$`, `$$...$$`, `\(...\)` or `\[...\]`
+fn math_length(text: &str) -> Option<usize> {
+    // Math can't go on past the end of a paragraph
+    let paragraph = &text[..text.find("\n\n").unwrap_or(text.len())];
+    let closed_by = |opening: &str, closing: &str| {
+        find_closing(&paragraph[opening.len()..], closing, |_| true).map(|end| opening.len() + end + closing.len())
+    };
+
+    if text.starts_with("$$") {
+        closed_by("$$", "$$")
+    } else if text.starts_with("\\(") {
+        closed_by("\\(", "\\)")
+    } else if text.starts_with("\\[") {
+        closed_by("\\[", "\\]")
+    } else if let Some(inner) = paragraph.strip_prefix('$') {
+        // As in Pandoc, a `$` only opens math right before something other than a space, and only
+        // closes it right after one and not before a digit, so that prices like $5 and $10 aren't math
+        if inner.starts_with(char::is_whitespace) {
+            return None;
+        }
+
+        find_closing(inner, "$", |end| {
+            let before = inner[..end].chars().next_back();
+            let after = inner[end + 1..].chars().next();
+
+            before.is_some_and(|before| !before.is_whitespace() && before != '\\')
+                && !after.is_some_and(|after| after.is_ascii_digit())
+        }).map(|end| end + 2)
+    } else {
+        None
+    }
+}
+
+// Where some closing delimiter first comes in some text, if it's allowed to close there, skipping
+// over any code
+fn find_closing<F: Fn(usize) -> bool>(text: &str, closing: &str, closes: F) -> Option<usize> {
+    let mut offset = 0;
+
+    while let Some(character) = text[offset..].chars().next() {
+        if text[offset..].starts_with(closing) && closes(offset) {
+            return Some(offset);
+        }
+
+        offset += if character == '`' {
+            code_length(&text[offset..])
+        } else {
+            character.len_utf8()
+        };
+    }
+
+    None
+}
+
+// The length of the code span or fence at the start of some text, up to the end of the matching
+// run of backticks - or just the backticks, if they're never matched
+fn code_length(text: &str) -> usize {
+    let ticks = text.len() - text.trim_start_matches('`').len();
+    let run = &text[..ticks];
+    let mut offset = ticks;
+
+    while let Some(found) = text[offset..].find(run) {
+        let end = offset + found + ticks;
+        let longer = text[end..].len() - text[end..].trim_start_matches('`').len();
+
+        if longer == 0 {
+            return end;
+        }
+        offset = end + longer;
+    }
+
+    ticks
+}
+
+/// Escape the characters that LaTeX treats specially
+pub fn escape_latex(latex: &mut String, text: &str) {
+    for character in text.chars() {
+        match character {
+            '\\' => latex.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '#' | '%' | '_' => {
+                latex.push('\\');
+                latex.push(character);
+            },
+            '^' => latex.push_str("\\^{}"),
+            '~' => latex.push_str("\\textasciitilde{}"),
+            _ => latex.push(character),
+        }
+    }
+}
+
+// URLs are taken as they are by `\href`, other than these
+fn escape_url(latex: &mut String, url: &str) {
+    for character in url.chars() {
+        if let '\\' | '{' | '}' | '#' | '%' = character {
+            latex.push('\\');
+        }
+        latex.push(character);
+    }
+}
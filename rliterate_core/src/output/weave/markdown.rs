@@ -21,10 +21,17 @@
 
 use pulldown_cmark as cmark;
 
-use output::canon::{BlockMap};
-use link::{LinkedFile, LinkedBlock, LinkedLine};
+use super::anchors::{Anchors, slugify, unique};
+use super::toc::{Entry, SectionNumbers, build_toc, build_entries, section_entries};
+use super::book::{Books, Part, build_navigation, build_contents, page};
+use super::highlight::{Highlighter, Language, language, escape_html};
+use output::canon::{BlockMap, CanonicalCodeBlock, FragmentKind};
+use parser::{BlockModifier, TableOfContents};
+use link::{LinkedFile, LinkedBlock, LinkedLine, LineSegment};
 
 use std::borrow::{Cow};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 use std::vec;
 
 // Internal markdown representation
@@ -35,38 +42,271 @@ pub struct MarkDown<'m> {
 }
 
 impl<'m> MarkDown<'m> {
-    pub fn build(settings: &super::Globals, file: &'m LinkedFile<'m>, block_map: &'m BlockMap) -> Self {
+    /// A page of its own - which, without any `books`, has no navigation or index of chapters
+    pub fn build(settings: &super::Globals, file_name: &Path, page: &Path, file: &'m LinkedFile<'m>, block_map: &'m BlockMap, books: Option<&Books>) -> Self {
         let mut file_contents : Vec<cmark::Event<'m>> = vec![];
+        let extension = settings.weave_type.extension();
+        let navigation = books.and_then(|books| books.navigation(file_name));
+
+        if let Some(navigation) = navigation {
+            file_contents.append(&mut build_navigation(navigation, page, extension));
+        }
     
         file_contents.append(&mut build_title(&file.title));
-        
-        for section in file.sections.iter() {
-            file_contents.append(&mut build_section_header(settings, section.name, section.depth));
-    
-            for block in section.blocks.iter() {
-                match block {
-                    &LinkedBlock::Code { ref name, ref lines, ..} => {
-                        file_contents.append(&mut build_code_block(settings, name, lines, block_map, &file.code_type));
-                    },
-                    &LinkedBlock::Prose { ref lines } => {
-                        file_contents.append(&mut lines.iter().flat_map(|line| {
-                            cmark::Parser::new(line.get_text())
-                        }).collect());
-                    },
-                }
-            }
+
+        let anchors = Anchors::build(file, block_map);
+        let numbers = SectionNumbers::build(file);
+
+        let toc = file.toc.or(settings.toc).unwrap_or(TableOfContents::Top);
+        file_contents.append(&mut build_toc(file, toc, &anchors, &numbers));
+
+        file_contents.append(&mut build_body(settings, file, block_map, &anchors, &numbers));
+
+        if let Some(chapters) = books.and_then(|books| books.contents(file_name)) {
+            file_contents.append(&mut build_contents(chapters, page, extension));
         }
-    
+
+        if let Some(navigation) = navigation {
+            file_contents.append(&mut build_navigation(navigation, page, extension));
+        }
+
         MarkDown {
             file_contents: file_contents,
         }
     }
 
+    /// A book and all of its chapters, as one document
+    pub fn build_book(settings: &super::Globals, parts: &'m [Part<'m>], block_maps: &'m [BlockMap]) -> Self {
+        let mut file_contents : Vec<cmark::Event<'m>> = vec![];
+        // The book itself is always the first part
+        let book = parts[0].file;
+
+        file_contents.append(&mut build_title(&book.title));
+
+        // Anchors and numbers are shared by every part, so that they don't clash
+        let mut taken = HashSet::new();
+        let mut counters = vec![];
+        let mut entries = vec![];
+        let mut built_parts = vec![];
+        let mut part_links = PartLinks::default();
+
+        for (part, block_map) in parts.iter().zip(block_maps.iter()) {
+            let part_anchor = if part.depth > 0 {
+                let slug = slugify(&part.file.title);
+                let slug = if slug.is_empty() { "chapter".to_owned() } else { slug };
+                let anchor = unique(slug, &mut taken);
+
+                entries.push(Entry {
+                    depth: part.depth - 1,
+                    text: part.file.title.clone(),
+                    anchor: anchor.clone(),
+                });
+                Some(anchor)
+            } else {
+                None
+            };
+
+            let anchors = Anchors::build_with(part.file, block_map, &mut taken);
+            let numbers = SectionNumbers::build_continuing(part.file, &mut counters);
+            entries.append(&mut section_entries(part.file, &anchors, &numbers, part.depth));
+
+            let page = page(&part.path, part.file).path;
+            part_links.add(&page, part.file, block_map, part_anchor.clone(), &anchors);
+
+            built_parts.push((part, page, block_map, part_anchor, anchors, numbers));
+        }
+
+        let toc = book.toc.or(settings.toc).unwrap_or(TableOfContents::Top);
+        file_contents.append(&mut build_entries(toc, entries));
+
+        let extension = settings.weave_type.extension();
+
+        for (part, page, block_map, part_anchor, anchors, numbers) in built_parts.into_iter() {
+            if let Some(part_anchor) = part_anchor {
+                // Chapters sit between the book's title and their sections
+                let level = (1 + part.depth).min(3) as i32;
+                file_contents.append(&mut vec![
+                    cmark::Event::Start(cmark::Tag::Header(level)),
+                    cmark::Event::InlineHtml(Cow::Owned(anchor_tag(&part_anchor))),
+                    cmark::Event::Text(Cow::Borrowed(&part.file.title)),
+                    cmark::Event::End(cmark::Tag::Header(level)),
+                ]);
+            }
+
+            let body = build_body(settings, part.file, block_map, &anchors, &numbers);
+            file_contents.extend(body.into_iter().map(|event| part_links.rewrite(&page, extension, event)));
+        }
+
+        MarkDown {
+            file_contents,
+        }
+    }
+
     pub fn into_iter(self) -> vec::IntoIter<cmark::Event<'m>> {
         self.file_contents.into_iter()
     }
 }
 
+/// Where links to the pages of a book's parts go, when the parts are all in one document
+#[derive(Default)]
+struct PartLinks {
+    /// The anchor that each part's page starts at, which the book itself doesn't have
+    parts: HashMap<PathBuf, Option<String>>,
+    /// The anchors of each part's own page, and the anchors that they have in the document
+    anchors: HashMap<PathBuf, HashMap<String, String>>,
+}
+
+impl PartLinks {
+    fn add(&mut self, page: &Path, file: &LinkedFile, block_map: &BlockMap, part_anchor: Option<String>, anchors: &Anchors) {
+        // The anchors are made the same way for the part's own page, but without having to avoid
+        // the ones in other parts
+        let page_anchors = Anchors::build(file, block_map);
+        let mut renamed = HashMap::new();
+
+        for section in file.sections.iter() {
+            renamed.insert(page_anchors.section(section.id).to_owned(), anchors.section(section.id).to_owned());
+
+            for block in section.blocks.iter() {
+                if let LinkedBlock::Code { name, .. } = block {
+                    if let (Some(page_anchor), Some(anchor)) = (page_anchors.block(name), anchors.block(name)) {
+                        renamed.insert(page_anchor.to_owned(), anchor.to_owned());
+                    }
+                }
+            }
+        }
+
+        self.parts.insert(page.to_path_buf(), part_anchor);
+        self.anchors.insert(page.to_path_buf(), renamed);
+    }
+
+    /// Point a link from the page `from` to one of the parts' pages, like `other.html#section`, at
+    /// where that part is in the document instead
+    fn rewrite<'a>(&self, from: &Path, extension: &str, event: cmark::Event<'a>) -> cmark::Event<'a> {
+        let rewrite_tag = |tag| match tag {
+            cmark::Tag::Link(destination, title) => match self.destination(from, extension, &destination) {
+                Some(anchor) => cmark::Tag::Link(Cow::Owned(anchor), title),
+                None => cmark::Tag::Link(destination, title),
+            },
+            tag => tag,
+        };
+
+        match event {
+            cmark::Event::Start(tag) => cmark::Event::Start(rewrite_tag(tag)),
+            cmark::Event::End(tag) => cmark::Event::End(rewrite_tag(tag)),
+            event => event,
+        }
+    }
+
+    fn destination(&self, from: &Path, extension: &str, destination: &str) -> Option<String> {
+        let (path, fragment) = match destination.find('#') {
+            Some(hash) => (&destination[..hash], Some(&destination[hash + 1..])),
+            None => (destination, None),
+        };
+
+        // Links within a page already use the document's anchors
+        if path.is_empty() || path.contains(':') || path.starts_with('/') {
+            return None;
+        }
+
+        let suffix = format!(".{}", extension);
+        if !path.ends_with(&suffix) {
+            return None;
+        }
+
+        let linked = from.parent().unwrap_or_else(|| Path::new("")).join(&path[..path.len() - suffix.len()]);
+        let linked = normalise(&linked)?;
+        let part_anchor = self.parts.get(&linked)?;
+
+        let anchor = fragment
+            .and_then(|fragment| self.anchors[&linked].get(fragment))
+            .or(part_anchor.as_ref());
+
+        Some(match anchor {
+            Some(anchor) => format!("#{}", anchor),
+            // The book starts at the top of the document
+            None => "#".to_owned(),
+        })
+    }
+}
+
+/// A relative path without any `.` or `..` in it, if it doesn't climb out of where it starts
+fn normalise(path: &Path) -> Option<PathBuf> {
+    let mut normalised = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalised.push(part),
+            Component::CurDir => {},
+            Component::ParentDir => if !normalised.pop() {
+                return None;
+            },
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(normalised)
+}
+
+// The sections of a file, with their prose and code
+fn build_body<'m>(settings: &super::Globals, file: &'m LinkedFile<'m>, block_map: &'m BlockMap, anchors: &Anchors, numbers: &SectionNumbers) -> Vec<cmark::Event<'m>> {
+    let mut body = vec![];
+
+    let cross_references = CrossReferences {
+        anchors,
+        numbers,
+        section_names: file.sections.iter().map(|section| (section.id, section.name)).collect(),
+        used_in: find_uses(file),
+    };
+
+    let mut defined_blocks = HashSet::new();
+    let mut anchored_blocks = HashSet::new();
+    
+    for section in file.sections.iter() {
+        if section.name.is_some() || !section.blocks.is_empty() {
+            body.append(&mut build_section_header(settings, section.name, section.depth, numbers.section(section.id), anchors.section(section.id)));
+        }
+
+        for block in section.blocks.iter() {
+            match block {
+                &LinkedBlock::Code { ref name, modifiers, ref lines } => {
+                    let kind = if modifiers.contains(BlockModifier::REDEF) {
+                        FragmentKind::Redefinition
+                    } else if defined_blocks.insert(name.as_str()) {
+                        FragmentKind::Definition
+                    } else {
+                        FragmentKind::Append
+                    };
+
+                    if !is_woven(name, modifiers, block_map) {
+                        trace!("Leaving the block \"{}\" out of the weave", name);
+                        continue;
+                    }
+
+                    // The first piece of a block shown in the weave is where links to it go
+                    let anchor = if anchored_blocks.insert(name.as_str()) {
+                        anchors.block(name)
+                    } else {
+                        None
+                    };
+
+                    let canonical = block_map.get(name.as_str());
+
+                    body.append(&mut build_block_heading(name, kind, modifiers, anchor, canonical));
+                    body.append(&mut build_code_block(settings, lines, anchors, block_language(name, canonical, &file.code_type), &file.code_type));
+                    if let Some(block) = canonical {
+                        body.append(&mut build_cross_references(name, block, &cross_references));
+                    }
+                },
+                &LinkedBlock::Prose { ref lines } => {
+                    body.append(&mut build_prose(lines, anchors));
+                },
+            }
+        }
+    }
+
+    body
+}
+
 fn build_title<'a>(title: &'a str) -> Vec<cmark::Event<'a>> {
     vec![
         cmark::Event::Start(cmark::Tag::Header(1)),
@@ -75,32 +315,326 @@ fn build_title<'a>(title: &'a str) -> Vec<cmark::Event<'a>> {
     ]
 }
 
-fn build_section_header<'a>(_settings: &super::Globals, name: Option<&'a str>, depth: usize) -> Vec<cmark::Event<'a>> {
+fn build_section_header<'a>(_settings: &super::Globals, name: Option<&'a str>, depth: usize, number: &str, anchor: &str) -> Vec<cmark::Event<'a>> {
+    let anchor = anchor_tag(anchor);
+    // There are only six levels of header, and sections start at the fourth
+    let level = (4 + depth).min(6) as i32;
+
     if let Some(name) = name {
         vec![
-            cmark::Event::Start(cmark::Tag::Header(4 + (depth as i32))),
-            cmark::Event::Text(Cow::Borrowed(name)),
-            cmark::Event::End(cmark::Tag::Header(4 + (depth as i32))),
+            cmark::Event::Start(cmark::Tag::Header(level)),
+            cmark::Event::InlineHtml(Cow::Owned(anchor)),
+            cmark::Event::Text(Cow::Owned(format!("{} {}", number, name))),
+            cmark::Event::End(cmark::Tag::Header(level)),
         ]
     } else {
-        vec![]
+        vec![
+            cmark::Event::Html(Cow::Owned(anchor + "\n")),
+        ]
     }
 }
 
-fn build_code_block<'a>(_settings: &super::Globals, _name: &'a str, lines: &'a [LinkedLine<'a>], _block_map: &'a BlockMap, code_type: &'a str) -> Vec<cmark::Event<'a>> {
-    let mut code_block : Vec<cmark::Event<'a>> = vec![];
+/// File blocks are highlighted by their extension, if it's known, and other blocks by the code type
+pub fn block_language(name: &str, canonical: Option<&CanonicalCodeBlock>, code_type: &str) -> Option<&'static Language> {
+    let by_extension = if canonical.is_some_and(|block| block.is_file()) {
+        Path::new(name).extension().and_then(|extension| language(&extension.to_string_lossy()))
+    } else {
+        None
+    };
+
+    by_extension.or_else(|| language(code_type))
+}
 
-    code_block.push(cmark::Event::Start(cmark::Tag::CodeBlock(Cow::Borrowed(code_type))));
-    code_block.append(&mut lines.iter().flat_map(|line| {
+/// Whether any of a block is shown in the weave
+pub fn is_woven(name: &str, modifiers: BlockModifier, block_map: &BlockMap) -> bool {
+    // The canonical block is missing only if linking failed, which would have stopped the weave
+    !modifiers.contains(BlockModifier::NOWEAVE) && block_map.get(name).is_none_or(|block| block.print_to_weave())
+}
+
+// The block's header, or just its anchor if the header is hidden
+fn build_block_heading<'a>(name: &'a str, kind: FragmentKind, modifiers: BlockModifier, anchor: Option<&str>, canonical: Option<&CanonicalCodeBlock>) -> Vec<cmark::Event<'a>> {
+    if !modifiers.contains(BlockModifier::NOHEADER) && canonical.is_none_or(|block| block.print_header()) {
+        let is_file = canonical.is_some_and(|block| block.is_file());
+        build_block_header(name, kind, is_file, anchor)
+    } else if let Some(anchor) = anchor {
         vec![
-            cmark::Event::Text(Cow::Borrowed(line.get_text())),
-            cmark::Event::SoftBreak,
+            cmark::Event::Start(cmark::Tag::Paragraph),
+            cmark::Event::InlineHtml(Cow::Owned(anchor_tag(anchor))),
+            cmark::Event::End(cmark::Tag::Paragraph),
         ]
-    }).collect());
-    code_block.push(cmark::Event::End(cmark::Tag::CodeBlock(Cow::Borrowed(code_type))));
+    } else {
+        vec![]
+    }
+}
+
+fn build_code_block<'a>(settings: &super::Globals, lines: &'a [LinkedLine<'a>], anchors: &Anchors, language: Option<&'static Language>, code_type: &'a str) -> Vec<cmark::Event<'a>> {
+    let mut code_block : Vec<cmark::Event<'a>> = vec![];
+
+    // Links and highlighting can't go inside Markdown code blocks, so only the HTML weave gets them
+    // - a Markdown compiler command is given the Markdown, which can't hold the HTML either
+    if let super::Type::HtmlViaMarkdown(None) = settings.weave_type {
+        code_block.push(cmark::Event::Html(Cow::Owned(build_highlighted_code(lines, anchors, language, code_type))));
+    } else {
+        code_block.push(cmark::Event::Start(cmark::Tag::CodeBlock(Cow::Borrowed(code_type))));
+        // Each line ends in a newline of its own, as a Markdown printer puts soft breaks on one line
+        code_block.append(&mut lines.iter().map(|line| {
+            cmark::Event::Text(Cow::Owned(format!("{}\n", line.get_text())))
+        }).collect());
+        code_block.push(cmark::Event::End(cmark::Tag::CodeBlock(Cow::Borrowed(code_type))));
+    }
     code_block.push(cmark::Event::SoftBreak);
 
     code_block
 }
 
+// Highlighted code, where `@{name}` links to the block
+fn build_highlighted_code(lines: &[LinkedLine], anchors: &Anchors, language: Option<&'static Language>, code_type: &str) -> String {
+    let code_type = language.map_or(code_type, |language| language.name());
+
+    let mut html = String::from("<pre class=\"prettyprint\"><code");
+    if !code_type.is_empty() {
+        html.push_str(" class=\"language-");
+        escape_html(&mut html, code_type);
+        html.push('"');
+    }
+    html.push('>');
 
+    let mut highlighter = Highlighter::new(language);
+    for line in lines.iter() {
+        for segment in line.segments() {
+            match segment {
+                LineSegment::Text(text) => highlighter.push(&mut html, text),
+                LineSegment::Link { name, .. } => {
+                    if let Some(anchor) = anchors.block(name) {
+                        html.push_str("<a href=\"#");
+                        escape_html(&mut html, anchor);
+                        html.push_str("\">{");
+                        escape_html(&mut html, name);
+                        html.push_str("}</a>");
+                    } else {
+                        html.push_str("@{");
+                        escape_html(&mut html, name);
+                        html.push('}');
+                    }
+                },
+            }
+        }
+        highlighter.end_line();
+        html.push('\n');
+    }
+
+    html.push_str("</code></pre>\n");
+    html
+}
+
+/// A block of prose as one Markdown document, which knows the line of the .lit file that each part
+/// of it came from
+pub struct ProseDocument {
+    /// The prose, with each `@{name}` of a woven block written as a link to it
+    pub markdown: String,
+    /// Where each line starts in the Markdown, and its number in the .lit file
+    lines: Vec<(usize, usize)>,
+}
+
+impl ProseDocument {
+    pub fn build(lines: &[LinkedLine], anchors: &Anchors) -> Self {
+        let mut markdown = String::new();
+        let mut line_starts = Vec::with_capacity(lines.len());
+
+        for line in lines.iter() {
+            line_starts.push((markdown.len(), line.line_number()));
+
+            for segment in line.segments() {
+                match segment {
+                    LineSegment::Text(slice) => markdown.push_str(slice),
+                    LineSegment::Link { name, .. } => match anchors.block(name) {
+                        Some(anchor) => markdown.push_str(&format!("[\\{{{}\\}}](#{})", escape_markdown(name), anchor)),
+                        None => markdown.push_str(&format!("@{{{}}}", name)),
+                    },
+                }
+            }
+            markdown.push('\n');
+        }
+
+        ProseDocument {
+            markdown,
+            lines: line_starts,
+        }
+    }
+
+    /// The line of the .lit file that an offset into the Markdown is on
+    pub fn line_number(&self, offset: usize) -> usize {
+        let index = match self.lines.binary_search_by_key(&offset, |&(start, _)| start) {
+            Ok(index) => index,
+            Err(index) => index.saturating_sub(1),
+        };
+
+        self.lines.get(index).map_or(0, |&(_, line_number)| line_number)
+    }
+}
+
+// Prose is parsed a block at a time, so that paragraphs, lists and quotes can span several lines
+fn build_prose<'a>(lines: &[LinkedLine], anchors: &Anchors) -> Vec<cmark::Event<'a>> {
+    let prose = ProseDocument::build(lines, anchors);
+    cmark::Parser::new_ext(&prose.markdown, cmark::OPTION_ENABLE_TABLES).map(into_owned).collect()
+}
+
+fn into_owned<'a>(event: cmark::Event) -> cmark::Event<'a> {
+    match event {
+        cmark::Event::Start(tag) => cmark::Event::Start(tag_into_owned(tag)),
+        cmark::Event::End(tag) => cmark::Event::End(tag_into_owned(tag)),
+        cmark::Event::Text(text) => cmark::Event::Text(Cow::Owned(text.into_owned())),
+        cmark::Event::Html(html) => cmark::Event::Html(Cow::Owned(html.into_owned())),
+        cmark::Event::InlineHtml(html) => cmark::Event::InlineHtml(Cow::Owned(html.into_owned())),
+        cmark::Event::FootnoteReference(name) => cmark::Event::FootnoteReference(Cow::Owned(name.into_owned())),
+        cmark::Event::SoftBreak => cmark::Event::SoftBreak,
+        cmark::Event::HardBreak => cmark::Event::HardBreak,
+    }
+}
+
+fn tag_into_owned<'a>(tag: cmark::Tag) -> cmark::Tag<'a> {
+    match tag {
+        cmark::Tag::Paragraph => cmark::Tag::Paragraph,
+        cmark::Tag::Rule => cmark::Tag::Rule,
+        cmark::Tag::Header(level) => cmark::Tag::Header(level),
+        cmark::Tag::BlockQuote => cmark::Tag::BlockQuote,
+        cmark::Tag::CodeBlock(info) => cmark::Tag::CodeBlock(Cow::Owned(info.into_owned())),
+        cmark::Tag::List(start) => cmark::Tag::List(start),
+        cmark::Tag::Item => cmark::Tag::Item,
+        cmark::Tag::FootnoteDefinition(name) => cmark::Tag::FootnoteDefinition(Cow::Owned(name.into_owned())),
+        cmark::Tag::Table(alignments) => cmark::Tag::Table(alignments),
+        cmark::Tag::TableHead => cmark::Tag::TableHead,
+        cmark::Tag::TableRow => cmark::Tag::TableRow,
+        cmark::Tag::TableCell => cmark::Tag::TableCell,
+        cmark::Tag::Emphasis => cmark::Tag::Emphasis,
+        cmark::Tag::Strong => cmark::Tag::Strong,
+        cmark::Tag::Code => cmark::Tag::Code,
+        cmark::Tag::Link(url, title) => cmark::Tag::Link(Cow::Owned(url.into_owned()), Cow::Owned(title.into_owned())),
+        cmark::Tag::Image(url, title) => cmark::Tag::Image(Cow::Owned(url.into_owned()), Cow::Owned(title.into_owned())),
+    }
+}
+
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        if character.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
+fn anchor_tag(anchor: &str) -> String {
+    format!("<a id=\"{}\"></a>", anchor)
+}
+
+// Like `{Block name} +=` - file blocks are shown as code, without the braces
+fn build_block_header<'a>(name: &'a str, kind: FragmentKind, is_file: bool, anchor: Option<&str>) -> Vec<cmark::Event<'a>> {
+    let mut header = vec![
+        cmark::Event::Start(cmark::Tag::Paragraph),
+    ];
+
+    if let Some(anchor) = anchor {
+        header.push(cmark::Event::InlineHtml(Cow::Owned(anchor_tag(anchor))));
+    }
+    header.push(cmark::Event::Start(cmark::Tag::Strong));
+
+    if is_file {
+        header.append(&mut vec![
+            cmark::Event::Start(cmark::Tag::Code),
+            cmark::Event::Text(Cow::Borrowed(name)),
+            cmark::Event::End(cmark::Tag::Code),
+        ]);
+    } else {
+        header.push(cmark::Event::Text(Cow::Owned(format!("{{{}}}", name))));
+    }
+
+    header.push(cmark::Event::End(cmark::Tag::Strong));
+
+    match kind {
+        FragmentKind::Definition => {},
+        FragmentKind::Append => header.push(cmark::Event::Text(Cow::Borrowed(" +="))),
+        FragmentKind::Redefinition => header.push(cmark::Event::Text(Cow::Borrowed(" -="))),
+    }
+
+    header.push(cmark::Event::End(cmark::Tag::Paragraph));
+    header
+}
+
+/// What's needed to say where a block is used, added to and redefined
+struct CrossReferences<'c> {
+    anchors: &'c Anchors,
+    numbers: &'c SectionNumbers,
+    section_names: HashMap<usize, Option<&'c str>>,
+    /// The sections that refer to each block
+    used_in: HashMap<&'c str, Vec<usize>>,
+}
+
+/// The sections that refer to each block
+pub fn find_uses<'a>(file: &LinkedFile<'a>) -> HashMap<&'a str, Vec<usize>> {
+    let mut used_in : HashMap<&'a str, Vec<usize>> = HashMap::new();
+
+    for section in file.sections.iter() {
+        for block in section.blocks.iter() {
+            if let LinkedBlock::Code { ref lines, .. } = block {
+                let links = lines.iter().flat_map(|line| line.segments()).filter_map(|segment| match segment {
+                    LineSegment::Link { name, .. } => Some(name),
+                    LineSegment::Text(_) => None,
+                });
+
+                for link in links {
+                    let sections = used_in.entry(link).or_default();
+                    if !sections.contains(&section.id) {
+                        sections.push(section.id);
+                    }
+                }
+            }
+        }
+    }
+
+    used_in
+}
+
+// Footers like "Used in §2, §3.1."
+fn build_cross_references<'a>(name: &str, block: &CanonicalCodeBlock, cross_references: &CrossReferences) -> Vec<cmark::Event<'a>> {
+    let footers : Vec<(&str, &[usize])> = vec![
+        ("Used in", cross_references.used_in.get(name).map_or(&[], |sections| &sections[..])),
+        ("Added to in", block.appended_to_in()),
+        ("Redefined in", block.redefined_in()),
+    ];
+
+    let mut events = vec![];
+
+    for (label, sections) in footers.into_iter().filter(|(_, sections)| !sections.is_empty()) {
+        if events.is_empty() {
+            events.push(cmark::Event::Start(cmark::Tag::Paragraph));
+        } else {
+            events.push(cmark::Event::HardBreak);
+        }
+
+        events.push(cmark::Event::Start(cmark::Tag::Emphasis));
+        events.push(cmark::Event::Text(Cow::Owned(format!("{} ", label))));
+
+        for (index, section) in sections.iter().enumerate() {
+            if index > 0 {
+                events.push(cmark::Event::Text(Cow::Borrowed(", ")));
+            }
+
+            let title = cross_references.section_names.get(section).cloned().flatten().unwrap_or_default();
+            let link = cmark::Tag::Link(Cow::Owned(format!("#{}", cross_references.anchors.section(*section))), Cow::Owned(title.to_owned()));
+            events.push(cmark::Event::Start(link.clone()));
+            events.push(cmark::Event::Text(Cow::Owned(format!("§{}", cross_references.numbers.section(*section)))));
+            events.push(cmark::Event::End(link));
+        }
+
+        events.push(cmark::Event::Text(Cow::Borrowed(".")));
+        events.push(cmark::Event::End(cmark::Tag::Emphasis));
+    }
+
+    if !events.is_empty() {
+        events.push(cmark::Event::End(cmark::Tag::Paragraph));
+    }
+
+    events
+}
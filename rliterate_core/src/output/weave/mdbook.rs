@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! mdBook output, where a book is woven into the source of an mdBook - a `book.toml`, and a `src`
+//! directory with a Markdown page for every chapter and a `SUMMARY.md` that lists them.
+//!
+//! mdBook makes its own navigation between the pages, so the pages are woven without any.
+
+use output;
+use output::canon::{BlockMap};
+use super::book::{Part, page};
+use super::markdown::{MarkDown, escape_markdown};
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Write the mdBook of a book, in the output directory of the book's `.lit` file
+pub fn write_book(settings: &super::Globals, parts: &[Part], block_maps: &[BlockMap]) -> output::Result<()> {
+    // The book itself is always the first part
+    let book = parts[0].file;
+    let root = settings.out_dir.join(&book.relative_directory);
+    let src = root.join("src");
+
+    let mut summary = String::from("# Summary\n\n");
+
+    for (part, block_map) in parts.iter().zip(block_maps.iter()) {
+        let page = page(&part.path, part.file);
+        let source = source_path(&page.path, &book.relative_directory);
+
+        let markdown = MarkDown::build(settings, &part.path, &page.path, part.file, block_map, None);
+        let mut printed_markdown = Vec::new();
+        super::print_markdown(&mut printed_markdown, markdown)?;
+
+        let filename = src.join(&source);
+        // Every page is in the `src` directory, so it has a parent
+        fs::DirBuilder::new().recursive(true).create(filename.parent().unwrap())?;
+        info!("Writing the mdBook page \"{}\"", filename.to_string_lossy());
+        output::write_if_changed(&filename, &printed_markdown)?;
+
+        let link = format!("[{}]({})", escape_markdown(part.file.title.as_str()), source.to_string_lossy().replace('\\', "/"));
+        if part.depth == 0 {
+            // The book's own page comes before the numbered chapters, like an introduction
+            summary.push_str(&format!("{}\n\n", link));
+        } else {
+            summary.push_str(&format!("{}- {}\n", "    ".repeat(part.depth - 1), link));
+        }
+    }
+
+    let filename = src.join("SUMMARY.md");
+    info!("Writing the mdBook summary \"{}\"", filename.to_string_lossy());
+    output::write_if_changed(&filename, summary.as_bytes())?;
+
+    let filename = root.join("book.toml");
+    info!("Writing the mdBook configuration \"{}\"", filename.to_string_lossy());
+    output::write_if_changed(&filename, build_config(&book.title).as_bytes())?;
+
+    Ok(())
+}
+
+// Where a page goes in the `src` directory - pages are laid out as their `.lit` files are around
+// the book's, except for any outside of the book's directory, which mdBook can't reach
+fn source_path(page: &Path, book_directory: &Path) -> PathBuf {
+    let source = page.strip_prefix(book_directory).ok()
+        .filter(|source| source.components().all(|component| matches!(component, Component::Normal(_))))
+        .map_or_else(|| {
+            warn!("\"{}\" is outside of its book's directory, so it's put at the top of the mdBook", page.to_string_lossy());
+            // Every page is named after its file
+            PathBuf::from(page.file_name().unwrap())
+        }, Path::to_path_buf);
+
+    source.with_extension("md")
+}
+
+fn build_config(title: &str) -> String {
+    let mut config = String::from("[book]\ntitle = \"");
+
+    for character in title.chars() {
+        match character {
+            '"' => config.push_str("\\\""),
+            '\\' => config.push_str("\\\\"),
+            character if character.is_control() => config.push_str(&format!("\\u{:04X}", character as u32)),
+            character => config.push(character),
+        }
+    }
+
+    config.push_str("\"\nsrc = \"src\"\n");
+    config
+}
@@ -19,48 +19,125 @@
  * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
  */
 
+mod anchors;
+mod book;
+pub use self::book::{Books};
+mod highlight;
+mod toc;
 mod markdown;
 use self::markdown::{MarkDown};
 mod html;
+mod latex;
+mod mdbook;
 
 use output;
 use output::css;
+use output::canon;
 use output::canon::{BlockMap};
 use link::{LinkedFile};
+use parser::{TableOfContents};
 
 use pulldown_cmark as cmark;
 use prettify_cmark;
 use subprocess;
 
-use std::path::{PathBuf};
-use std::fs;
+use std::collections::{HashMap};
+use std::path::{Path, PathBuf};
 use std::io::{Write};
 
 pub struct Globals {
     pub weave_type: Type,
     pub out_dir: PathBuf,
     pub css: css::Globals,
+    /// Where the table of contents goes, unless a file says otherwise with `@toc`
+    pub toc: Option<TableOfContents>,
+    /// Weave each book and all of its chapters into a single document
+    pub single_page: bool,
 }
 
 pub enum Type {
     Markdown,
     HtmlViaMarkdown(Option<String>),
+    Latex(CodeEnvironment),
+    /// The source of an mdBook for each book, with a Markdown page for each chapter
+    MdBook,
 }
 
-pub fn weave_file_with_blocks<'a>(settings: &Globals, file_name: &PathBuf, file: &LinkedFile<'a>, block_map: &BlockMap) -> output::Result<()> {
+/// The LaTeX environment that code is woven into
+#[derive(Clone, Copy)]
+pub enum CodeEnvironment {
+    /// `lstlisting`, from the `listings` package
+    Listings,
+    /// `minted`, which highlights code with Pygments, and so needs LaTeX to be run with `-shell-escape`
+    Minted,
+}
+
+impl Type {
+    /// The extension of the files woven with this type
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Type::Markdown | Type::MdBook => "md",
+            Type::HtmlViaMarkdown(_) => "html",
+            Type::Latex(_) => "tex",
+        }
+    }
+}
+
+pub fn weave_file_with_blocks<'a>(settings: &Globals, file_name: &PathBuf, file: &LinkedFile<'a>, block_map: &BlockMap, books: &Books) -> output::Result<()> {
     trace!("Starting the weave...");
+    let page = book::page(file_name, file);
+
+    if let Type::Latex(environment) = settings.weave_type {
+        let latex = latex::build(settings, environment, file, block_map, books.contents(file_name));
+        write_latex(settings, file, &page.path, latex)?;
+    } else {
+        if let Type::MdBook = settings.weave_type {
+            warn!("\"{}\" isn't part of a book, so it is woven as a Markdown page outside of any mdBook", file_name.to_string_lossy());
+        }
+
+        let markdown = MarkDown::build(settings, file_name, &page.path, file, block_map, Some(books));
+        write_markdown(settings, file, &page.path, markdown)?;
+    }
+
+    trace!("Finished the weave");
+    Ok(())
+}
+
+/// Weave a book and all of its chapters together, into the book's page or into an mdBook
+pub fn weave_book<'a>(settings: &Globals, file_name: &Path, file_map: &HashMap<&'a PathBuf, LinkedFile<'a>>) -> output::Result<()> {
+    trace!("Starting the weave of the whole book...");
+    let parts = book::parts(file_name, file_map);
+    let block_maps : Vec<BlockMap> = parts.iter().map(|part| canon::canonicalise_code_blocks(&part.file.sections[..])).collect();
+
+    let file = parts[0].file;
+    let page = book::page(file_name, file);
+
     match settings.weave_type {
-        Type::HtmlViaMarkdown(ref maybe_command) => {
-            let markdown = MarkDown::build(settings, file, block_map);
-        
-            let html_dir = settings.out_dir.join(&file.relative_directory); 
-            std::fs::DirBuilder::new().recursive(true).create(&html_dir)?;
+        Type::Latex(environment) => {
+            let latex = latex::build_book(settings, environment, &parts, &block_maps);
+            write_latex(settings, file, &page.path, latex)?;
+        },
+        Type::MdBook => mdbook::write_book(settings, &parts, &block_maps)?,
+        Type::Markdown | Type::HtmlViaMarkdown(_) => {
+            let markdown = MarkDown::build_book(settings, &parts, &block_maps);
+            write_markdown(settings, file, &page.path, markdown)?;
+        },
+    }
+
+    trace!("Finished the weave of the whole book");
+    Ok(())
+}
 
-            let mut html_filename = html_dir.join(file_name.file_stem().unwrap());
-            html_filename.set_extension("html");
-            info!("Writing HTML documentation to \"{}\"", html_filename.to_string_lossy());
+fn write_markdown<'m>(settings: &Globals, file: &LinkedFile, page: &Path, markdown: MarkDown<'m>) -> output::Result<()> {
+    let out_dir = settings.out_dir.join(&file.relative_directory);
+    std::fs::DirBuilder::new().recursive(true).create(&out_dir)?;
 
-            let html_file = fs::OpenOptions::new().create(true).truncate(true).write(true).open(html_filename)?;
+    let mut filename = settings.out_dir.join(page);
+    filename.set_extension(settings.weave_type.extension());
+
+    match settings.weave_type {
+        Type::HtmlViaMarkdown(ref maybe_command) => {
+            info!("Writing HTML documentation to \"{}\"", filename.to_string_lossy());
 
             let compiled_markdown = if let Some(ref command) = maybe_command {
                 call_markdown_compiler(command, markdown)
@@ -68,24 +145,33 @@ pub fn weave_file_with_blocks<'a>(settings: &Globals, file_name: &PathBuf, file:
                 compile_markdown(markdown)
             }?;
 
-            html::print(html_file, compiled_markdown, &file.title, &settings.css)?;
+            let mut html = Vec::new();
+            html::print(&mut html, compiled_markdown, &file.title, &settings.css)?;
+            output::write_if_changed(&filename, &html)?;
         },
-        Type::Markdown => {
-            let markdown = MarkDown::build(settings, file, block_map);
-            
-            let md_dir = settings.out_dir.join(&file.relative_directory); 
-            std::fs::DirBuilder::new().recursive(true).create(&md_dir)?;
+        Type::Markdown | Type::MdBook => {
+            info!("Writing Markdown documentation to \"{}\"", filename.to_string_lossy());
+
+            let mut printed_markdown = Vec::new();
+            print_markdown(&mut printed_markdown, markdown)?;
+            output::write_if_changed(&filename, &printed_markdown)?;
+        },
+        Type::Latex(_) => unreachable!("LaTeX is woven without going through Markdown"),
+    }
+
+    Ok(())
+}
 
-            let mut md_filename = md_dir.join(file_name.file_stem().unwrap());
-            md_filename.set_extension("md");
-            info!("Writing Markdown documentation to \"{}\"", md_filename.to_string_lossy());
+fn write_latex(settings: &Globals, file: &LinkedFile, page: &Path, latex: String) -> output::Result<()> {
+    let out_dir = settings.out_dir.join(&file.relative_directory);
+    std::fs::DirBuilder::new().recursive(true).create(&out_dir)?;
 
-            let md_file = fs::OpenOptions::new().create(true).truncate(true).write(true).open(md_filename)?;
+    let mut filename = settings.out_dir.join(page);
+    filename.set_extension(settings.weave_type.extension());
+
+    info!("Writing LaTeX documentation to \"{}\"", filename.to_string_lossy());
+    output::write_if_changed(&filename, latex.as_bytes())?;
 
-            print_markdown(md_file, markdown)?;
-        }
-    }
-    trace!("Finished the weave");
     Ok(())
 }
 
@@ -97,13 +183,8 @@ impl From<subprocess::PopenError> for output::Error {
 
 fn call_markdown_compiler<'m>(command: &str, markdown: MarkDown<'m>) -> output::Result<String> {
     // Setup the markdown to be fed into the command
-    let mut printed_markdown = String::new();
-    
-    {
-        let mut pretty_printer = prettify_cmark::PrettyPrinter::new(&mut printed_markdown);
-        pretty_printer.push_events(markdown.into_iter()).unwrap();
-    }
-   
+    let printed_markdown = pretty_print(markdown);
+
     trace!("Invoking the requested markdown compiler \"{}\"...", command);
     let process_result = subprocess::Exec::shell(command)
         .stdin(printed_markdown.as_str())
@@ -134,9 +215,87 @@ fn compile_markdown<'m>(markdown: MarkDown<'m>) -> output::Result<String> {
     Ok(compiled_html)
 }
 
-fn print_markdown<'m>(mut file: fs::File, markdown: MarkDown<'m>) -> output::Result<()> {
-    let mut pretty_printer = prettify_cmark::PrettyPrinter::new(String::new());
-    pretty_printer.push_events(markdown.into_iter()).unwrap();
-    write!(file, "{}", pretty_printer.into_inner())?;
+fn print_markdown<'m, W: Write>(file: &mut W, markdown: MarkDown<'m>) -> output::Result<()> {
+    write!(file, "{}", pretty_print(markdown))?;
     Ok(())
 }
+
+// prettify_cmark leaves tables out, so they are written back out as Markdown text here
+fn pretty_print<'m>(markdown: MarkDown<'m>) -> String {
+    let mut pretty_printer = prettify_cmark::PrettyPrinter::new(String::new());
+    let mut table : Option<MarkdownTable> = None;
+
+    for event in markdown.into_iter() {
+        match event {
+            cmark::Event::Start(cmark::Tag::Table(alignments)) => {
+                table = Some(MarkdownTable::new(alignments));
+            },
+            cmark::Event::End(cmark::Tag::Table(_)) => {
+                if let Some(table) = table.take() {
+                    pretty_printer.push_event(cmark::Event::Start(cmark::Tag::Paragraph)).unwrap();
+                    pretty_printer.push_event(cmark::Event::Text(table.into_text().into())).unwrap();
+                    pretty_printer.push_event(cmark::Event::End(cmark::Tag::Paragraph)).unwrap();
+                }
+            },
+            event => match table {
+                Some(ref mut table) => table.push_event(event),
+                None => pretty_printer.push_event(event).unwrap(),
+            },
+        }
+    }
+
+    pretty_printer.into_inner()
+}
+
+struct MarkdownTable {
+    alignments: Vec<cmark::Alignment>,
+    /// The header, and then each row, as the Markdown of each of its cells
+    rows: Vec<Vec<String>>,
+    cell: Option<prettify_cmark::PrettyPrinter>,
+}
+
+impl MarkdownTable {
+    fn new(alignments: Vec<cmark::Alignment>) -> Self {
+        MarkdownTable {
+            alignments,
+            rows: vec![],
+            cell: None,
+        }
+    }
+
+    fn push_event<'m>(&mut self, event: cmark::Event<'m>) {
+        match event {
+            cmark::Event::Start(cmark::Tag::TableHead) | cmark::Event::Start(cmark::Tag::TableRow) => self.rows.push(vec![]),
+            cmark::Event::Start(cmark::Tag::TableCell) => self.cell = Some(prettify_cmark::PrettyPrinter::default()),
+            cmark::Event::End(cmark::Tag::TableCell) => {
+                if let (Some(cell), Some(row)) = (self.cell.take(), self.rows.last_mut()) {
+                    row.push(cell.into_inner().trim().replace('|', "\\|"));
+                }
+            },
+            event => if let Some(ref mut cell) = self.cell {
+                cell.push_event(event).unwrap();
+            },
+        }
+    }
+
+    fn into_text(self) -> String {
+        let delimiters : Vec<&str> = self.alignments.iter()
+            .map(|alignment| match alignment {
+                cmark::Alignment::None => "---",
+                cmark::Alignment::Left => ":--",
+                cmark::Alignment::Center => ":-:",
+                cmark::Alignment::Right => "--:",
+            })
+            .collect();
+
+        let mut lines = vec![];
+        for (index, row) in self.rows.iter().enumerate() {
+            lines.push(format!("| {} |", row.join(" | ")));
+            if index == 0 {
+                lines.push(format!("| {} |", delimiters.join(" | ")));
+            }
+        }
+
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Section numbers, like `1.2` for the second `@ss` section under the first `@s` one, and the
+//! table of contents which lists the sections by them.
+
+use parser::{TableOfContents};
+use link::{LinkedFile};
+use super::anchors::{Anchors};
+
+use pulldown_cmark as cmark;
+
+use std::borrow::{Cow};
+use std::collections::{HashMap};
+
+pub struct SectionNumbers {
+    numbers: HashMap<usize, String>,
+}
+
+impl SectionNumbers {
+    pub fn build(file: &LinkedFile) -> Self {
+        SectionNumbers::build_continuing(file, &mut vec![])
+    }
+
+    /// Number the sections of a file carrying on from the `counters` of the previous file, as when
+    /// a whole book is woven together
+    pub fn build_continuing(file: &LinkedFile, counters: &mut Vec<usize>) -> Self {
+        let mut numbers = HashMap::new();
+
+        for section in file.sections.iter() {
+            // The implicit section before the first `@s` isn't counted
+            if section.id == 0 {
+                numbers.insert(section.id, "0".to_owned());
+                continue;
+            }
+
+            counters.resize(section.depth + 1, 0);
+            counters[section.depth] += 1;
+
+            let number = counters.iter().map(usize::to_string).collect::<Vec<_>>().join(".");
+            numbers.insert(section.id, number);
+        }
+
+        SectionNumbers {
+            numbers,
+        }
+    }
+
+    pub fn section(&self, id: usize) -> &str {
+        // Every section gets a number when they are built
+        &self.numbers[&id]
+    }
+}
+
+/// A line in a table of contents
+pub struct Entry {
+    /// How far the entry is nested, from 0
+    pub depth: usize,
+    pub text: String,
+    pub anchor: String,
+}
+
+/// The entries for the named sections of a file, nested `depth` deep
+pub fn section_entries(file: &LinkedFile, anchors: &Anchors, numbers: &SectionNumbers, depth: usize) -> Vec<Entry> {
+    file.sections.iter()
+        .filter(|section| section.id != 0)
+        .filter_map(|section| section.name.map(|name| Entry {
+            depth: depth + section.depth,
+            text: format!("{} {}", numbers.section(section.id), name),
+            anchor: anchors.section(section.id).to_owned(),
+        }))
+        .collect()
+}
+
+pub fn build_toc<'a>(file: &LinkedFile, placement: TableOfContents, anchors: &Anchors, numbers: &SectionNumbers) -> Vec<cmark::Event<'a>> {
+    build_entries(placement, section_entries(file, anchors, numbers, 0))
+}
+
+/// A nested list of links to the entries, inside a `<nav>` - which the Markdown weave leaves out,
+/// so a sidebar is only a sidebar in HTML
+pub fn build_entries<'a>(placement: TableOfContents, entries: Vec<Entry>) -> Vec<cmark::Event<'a>> {
+    let nav = match placement {
+        TableOfContents::None => return vec![],
+        TableOfContents::Top => "<nav class=\"toc\">\n",
+        TableOfContents::Sidebar => "<nav class=\"toc toc-sidebar\">\n",
+    };
+
+    let mut events = vec![];
+    // How deep the list currently is, starting from 1 for the outermost list
+    let mut level = 0_usize;
+
+    for entry in entries.into_iter() {
+        // Skipped depths, like an `@sss` straight after an `@s`, only nest one level further
+        let depth = (entry.depth + 1).min(level + 1);
+
+        if depth > level {
+            events.push(cmark::Event::Start(cmark::Tag::List(None)));
+        } else {
+            events.push(cmark::Event::End(cmark::Tag::Item));
+            for _ in depth..level {
+                events.push(cmark::Event::End(cmark::Tag::List(None)));
+                events.push(cmark::Event::End(cmark::Tag::Item));
+            }
+        }
+        level = depth;
+
+        let link = cmark::Tag::Link(Cow::Owned(format!("#{}", entry.anchor)), Cow::Borrowed(""));
+        // Each entry is a paragraph, as the Markdown weave would otherwise put a nested list on the
+        // same line as its parent
+        events.push(cmark::Event::Start(cmark::Tag::Item));
+        events.push(cmark::Event::Start(cmark::Tag::Paragraph));
+        events.push(cmark::Event::Start(link.clone()));
+        events.push(cmark::Event::Text(Cow::Owned(entry.text)));
+        events.push(cmark::Event::End(link));
+        events.push(cmark::Event::End(cmark::Tag::Paragraph));
+    }
+    if level == 0 {
+        return vec![];
+    }
+
+    for _ in 1..level {
+        events.push(cmark::Event::End(cmark::Tag::Item));
+        events.push(cmark::Event::End(cmark::Tag::List(None)));
+    }
+    events.push(cmark::Event::End(cmark::Tag::Item));
+    events.push(cmark::Event::End(cmark::Tag::List(None)));
+
+    events.insert(0, cmark::Event::Html(Cow::Borrowed(nav)));
+    events.push(cmark::Event::Html(Cow::Borrowed("</nav>\n")));
+    events
+}
@@ -21,12 +21,16 @@
 
 use parser;
 use parser::{ParseState, get_input_file};
-use parser::grammar::{LitBlock, CodeBlock, Command, BlockModifier};
+use parser::error_format::{ErrorFormat};
+use parser::grammar::{LitBlock, CodeBlock, Command, BlockModifier, ExpansionMode, LineEnding, TableOfContents};
 use output::css;
 use output::css::{CustomCss};
 
 use std::path::{Path, PathBuf};
 
+/// The banner text for `@banner` and `--banner` without any text of their own
+pub const DEFAULT_BANNER : &str = "Generated from %f, do not edit";
+
 #[macro_use]
 mod macros {
     #[macro_export]
@@ -65,8 +69,25 @@ pub struct Metadata {
     pub code_type: String,
     pub file_extension: String,
     pub comment_type: Option<FormatFn<String>>,
+    /// The comments that prose is written into the code output as - if not given, prose uses the
+    /// `comment_type`
+    pub doc_comment_type: Option<FormatFn<String>>,
+    /// Write the prose before every code block into the code output, rather than only before
+    /// blocks marked with `docComment`
+    pub prose_comments: bool,
     pub line_number_format: Option<FormatFn<usize>>,
     pub compiler: Option<CompilerSettings>,
+    /// A command which formats the code output, from stdin to stdout
+    pub formatter: Option<String>,
+    pub expansion_mode: ExpansionMode,
+    pub line_ending: Option<LineEnding>,
+    /// The line ending that the .lit file itself uses
+    pub source_line_ending: LineEnding,
+    pub strip_trailing_whitespace: bool,
+    pub final_newline: bool,
+    pub banner: Option<String>,
+    pub block_markers: bool,
+    pub toc: Option<TableOfContents>,
     pub book_status: BookStatus,
 }
 
@@ -85,13 +106,23 @@ impl std::ops::Deref for LitFile {
 
 
 impl LitFile {
-    pub fn parse<'a>(parse_state: &mut ParseState, lines: Vec<LitBlock<'a>>) -> parser::Result<(Self, css::Globals)> {
+    pub fn parse<'a>(parse_state: &mut ParseState, file_path: &Path, relative_directory: &Path, lines: Vec<LitBlock<'a>>) -> parser::Result<(Self, css::Globals)> {
         let mut title = None;
         let mut code_type_and_file_extension = None;
         let mut comment_type = None;
         let mut compiler_command = None;
         let mut error_format = None;
         let mut line_number_format = None;
+        let mut expansion_mode = None;
+        let mut line_ending = None;
+        let mut strip_trailing_whitespace = false;
+        let mut final_newline = false;
+        let mut banner = None;
+        let mut block_markers = false;
+        let mut doc_comment_type = None;
+        let mut prose_comments = false;
+        let mut formatter = None;
+        let mut toc = None;
         let mut is_book = false;
         let mut custom_css = CustomCss::None;
         let mut custom_colorscheme = None;
@@ -122,7 +153,7 @@ impl LitFile {
                         once!(compiler_command, command.to_owned())
                     },
                     Command::ErrorFormat(formatter) => {
-                        once!(error_format, generate_error_format(formatter))
+                        once!(error_format, generate_error_format(formatter)?)
                     },
                     Command::LineNumbers(formatter) => {
                         once!(line_number_format, generate_line_number_format(formatter))
@@ -154,6 +185,36 @@ impl LitFile {
                     Command::Colorscheme(css_file) => {
                         once!(custom_colorscheme, css_file.to_owned())
                     },
+                    Command::Expansion(mode) => {
+                        once!(expansion_mode, mode)
+                    },
+                    Command::LineEndings(ending) => {
+                        once!(line_ending, ending)
+                    },
+                    Command::StripTrailingWhitespace => {
+                        strip_trailing_whitespace = true;
+                    },
+                    Command::FinalNewline => {
+                        final_newline = true;
+                    },
+                    Command::Banner(text) => {
+                        once!(banner, text.unwrap_or(DEFAULT_BANNER).to_owned())
+                    },
+                    Command::BlockMarkers => {
+                        block_markers = true;
+                    },
+                    Command::DocCommentType(formatter) => {
+                        once!(doc_comment_type, generate_comment_type(formatter))
+                    },
+                    Command::ProseComments => {
+                        prose_comments = true;
+                    },
+                    Command::Formatter(command) => {
+                        once!(formatter, command.to_owned())
+                    },
+                    Command::Toc(placement) => {
+                        once!(toc, placement)
+                    },
                 },
                 LitBlock::Code(code) => {
                     debug!("Added a code block to section \"{:?}\", id: {}", 
@@ -161,38 +222,43 @@ impl LitFile {
                            current_section.id);
                     current_section.blocks.push(Block::parse_code(code));
                 },
-                LitBlock::Prose(lines) => {
+                LitBlock::Prose { first_line, lines } => {
                     debug!("Added a prose block to section \"{:?}\", id: {}", 
                            current_section.name.as_str(),
                            current_section.id);
-                    current_section.blocks.push(Block::parse_prose(lines));
+                    current_section.blocks.push(Block::parse_prose(first_line, lines));
                 },
                 LitBlock::Chapter { title: chapter_title, file_name: chapter_file } => {
-                    let file_path = get_input_file(Path::new(chapter_file))?;
+                    let chapter_file = Path::new(chapter_file);
                    
-                    if file_path.is_absolute() {
+                    if chapter_file.is_absolute() {
                       return Err(parser::Error::BadChapterPath);
                     }
-                    //Constrain the borrow region
-                    {
-                        let relative_directory = file_path.parent().unwrap();
-                        parse_state.parse_file(&file_path, &relative_directory)?;
-                    }
 
-                    parse_state.file_map.get_mut(&file_path).unwrap().set_title(chapter_title.to_owned());
+                    // Chapters are found relative to the book, and woven next to it
+                    let book_directory = file_path.parent().unwrap_or_else(|| Path::new(""));
+                    let chapter_path = get_input_file(&book_directory.join(chapter_file))?;
+                    let chapter_directory = relative_directory.join(chapter_file.parent().unwrap_or_else(|| Path::new("")));
+                    parse_state.parse_chapter(&chapter_path, &chapter_directory)?;
 
-                    chapters.push(file_path);
+                    parse_state.file_map.get_mut(&chapter_path).unwrap().set_title(chapter_title.to_owned());
+
+                    chapters.push(chapter_path);
                 },
             }
         }
     
-        let compiler_settings = compiler_command.and_then(|command| {
-            let error_format = error_format.or(try_guess_error_format(&command))?;
+        let compiler_settings = compiler_command.map(|command| {
+            let error_format = error_format.or_else(|| try_guess_error_format(&command));
+
+            if error_format.is_none() {
+                warn!("Couldn't pick an error format for the compiler command \"{}\", so its output won't point at the .lit file - add an @error_format command to fix this", command);
+            }
 
-            Some(CompilerSettings {
+            CompilerSettings {
                 command: command, 
-                formatter: error_format
-            })
+                error_format,
+            }
         });
 
         // Finish off the last section
@@ -217,8 +283,19 @@ impl LitFile {
                     code_type: code_type,
                     file_extension: file_extension,
                     comment_type: comment_type,
+                    doc_comment_type,
+                    prose_comments,
                     line_number_format: line_number_format,
                     compiler: compiler_settings,
+                    formatter,
+                    expansion_mode: expansion_mode.unwrap_or(ExpansionMode::Repeat),
+                    line_ending,
+                    source_line_ending: LineEnding::Lf,
+                    strip_trailing_whitespace,
+                    final_newline,
+                    banner,
+                    block_markers,
+                    toc,
                     book_status: book_status,
                 },
                 sections: sections,
@@ -241,8 +318,7 @@ pub enum BookStatus {
 
 pub struct CompilerSettings {
     pub command: String,
-    #[allow(dead_code)] // TODO: make use of this field
-    formatter: &'static dyn Fn(String, String, String, String) -> String
+    pub error_format: Option<ErrorFormat>,
 }
 
 pub enum SectionName {
@@ -275,8 +351,8 @@ pub struct Section {
 }
 
 pub enum Block {
-    Code { name: String, modifiers: BlockModifier, lines: Vec<String> },
-    Prose { lines: Vec<String> }
+    Code { name: String, modifiers: BlockModifier, first_line: usize, lines: Vec<String> },
+    Prose { first_line: usize, lines: Vec<String> }
 }
 
 impl Block {
@@ -284,23 +360,25 @@ impl Block {
         Block::Code {
             name : code_block.block_name.to_owned(),
             modifiers : code_block.modifiers,
+            first_line : code_block.first_line,
             lines : code_block.contents.into_iter().map(|slice| slice.to_owned()).collect()
         }
     }
 
-    fn parse_prose<'a>(prose_block: Vec<&str>) -> Self {
+    fn parse_prose<'a>(first_line: usize, prose_block: Vec<&str>) -> Self {
         Block::Prose {
+            first_line,
             lines : prose_block.into_iter().map(|slice| slice.to_owned()).collect()
         }
     }
 }
 
-fn try_guess_error_format(_compiler: &String) -> Option<&'static dyn Fn(String, String, String, String) -> String> {
-    None
+fn try_guess_error_format(compiler: &str) -> Option<ErrorFormat> {
+    ErrorFormat::guess(compiler)
 }
 
-fn generate_error_format(_format_string: &str) -> &'static dyn Fn(String, String, String, String) -> String {
-    panic!()
+fn generate_error_format(format_string: &str) -> parser::Result<ErrorFormat> {
+    ErrorFormat::parse(format_string)
 }
 
 fn generate_comment_type(format_string: &str) -> FormatFn<String> {
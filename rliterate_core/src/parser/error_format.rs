@@ -0,0 +1,252 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Error formats, which describe where a compiler's messages say the problem is.
+//!
+//! These work like `@error_format` in `literate`: a pattern is matched against a whole line of
+//! compiler output, with placeholders for the interesting parts.
+//!
+//!  * `%f` - the file name
+//!  * `%l` - the line number
+//!  * `%c` - the column number
+//!  * `%m` - the message, which runs to the end of the line
+//!  * `%s` - any other text, which is skipped
+//!  * `%%` - a literal `%`
+//!
+//! Any other character matches itself, including one escaped with a `\`.
+//!
+//! There are also presets for some common compilers, which can be picked out from the compiler
+//! command.
+
+use parser;
+
+use std::ops::Range;
+use std::path::Path;
+
+/// The patterns that each preset is made of
+const PRESETS : &[(&str, &[&str])] = &[
+    ("rustc", &["%s--> %f:%l:%c", "%s::: %f:%l:%c", "%f:%l:%c: %m"]),
+    ("gcc", &["%f:%l:%c: %m", "%f:%l: %m"]),
+    ("clang", &["%f:%l:%c: %m", "%f:%l: %m"]),
+    ("go", &["%f:%l:%c: %m", "%f:%l: %m"]),
+    ("javac", &["%f:%l: %m"]),
+];
+
+/// The programs that each preset is picked for
+const PROGRAMS : &[(&str, &[&str])] = &[
+    ("rustc", &["rustc", "cargo"]),
+    ("gcc", &["gcc", "g++", "cc", "c++"]),
+    ("clang", &["clang", "clang++"]),
+    ("go", &["go"]),
+    ("javac", &["javac"]),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Placeholder {
+    File,
+    Line,
+    Column,
+    Message,
+    Skip,
+}
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug)]
+pub struct ErrorFormat {
+    // A line matches the format if it matches any of these
+    alternatives: Vec<Vec<Part>>,
+}
+
+/// The parts of a line of compiler output that an error format picked out, as byte ranges
+#[derive(Debug, PartialEq)]
+pub struct CompilerMessage {
+    pub file: Range<usize>,
+    pub line: Range<usize>,
+    pub column: Option<Range<usize>>,
+    pub message: Option<Range<usize>>,
+}
+
+impl ErrorFormat {
+    pub fn parse(pattern: &str) -> parser::Result<Self> {
+        Ok(ErrorFormat {
+            alternatives: vec![parse_pattern(pattern)?],
+        })
+    }
+
+    /// Get the preset for a compiler - one of `rustc`, `gcc`, `clang`, `go` or `javac`
+    pub fn preset(name: &str) -> Option<Self> {
+        let (_, patterns) = PRESETS.iter().find(|(preset, _)| *preset == name)?;
+
+        Some(ErrorFormat {
+            // The presets are known to be valid
+            alternatives: patterns.iter().map(|pattern| parse_pattern(pattern).unwrap()).collect(),
+        })
+    }
+
+    /// Pick a preset based on the programs that a compiler command runs
+    pub fn guess(command: &str) -> Option<Self> {
+        let (preset, _) = command.split_whitespace()
+            .filter_map(|word| Path::new(word).file_name()?.to_str())
+            .map(|program| program.trim_end_matches(".exe"))
+            .filter_map(|program| PROGRAMS.iter().find(|(_, programs)| {
+                programs.iter().any(|known| is_program(program, known))
+            }))
+            .next()?;
+
+        info!("Using the {} error format for the compiler command \"{}\"", preset, command);
+        ErrorFormat::preset(preset)
+    }
+
+    /// Match a whole line of compiler output against the format
+    pub fn match_line(&self, line: &str) -> Option<CompilerMessage> {
+        let mut captures = Vec::new();
+
+        let matched = self.alternatives.iter().any(|parts| {
+            captures.clear();
+            match_parts(parts, line, 0, &mut captures)
+        });
+
+        if matched {
+            let find = |wanted: Placeholder| captures.iter()
+                .find(|(placeholder, _)| *placeholder == wanted)
+                .map(|(_, range)| range.clone());
+
+            Some(CompilerMessage {
+                file: find(Placeholder::File)?,
+                line: find(Placeholder::Line)?,
+                column: find(Placeholder::Column),
+                message: find(Placeholder::Message),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+// Allows for versioned programs, like `gcc-12`
+fn is_program(program: &str, known: &str) -> bool {
+    match program.strip_prefix(known) {
+        Some("") => true,
+        Some(version) => version.starts_with('-') && version[1..].chars().all(|c| c.is_ascii_digit() || c == '.'),
+        None => false,
+    }
+}
+
+fn parse_pattern(pattern: &str) -> parser::Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut characters = pattern.chars();
+
+    while let Some(character) = characters.next() {
+        let placeholder = match character {
+            '%' => match characters.next() {
+                Some('f') => Placeholder::File,
+                Some('l') => Placeholder::Line,
+                Some('c') => Placeholder::Column,
+                Some('m') => Placeholder::Message,
+                Some('s') => Placeholder::Skip,
+                Some('%') => {
+                    literal.push('%');
+                    continue;
+                },
+                other => {
+                    error!("Unknown placeholder \"%{}\" in the error format \"{}\"", other.map_or(String::new(), |c| c.to_string()), pattern);
+                    return Err(parser::Error::FormatError);
+                },
+            },
+            '\\' => {
+                literal.extend(characters.next());
+                continue;
+            },
+            other => {
+                literal.push(other);
+                continue;
+            },
+        };
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(std::mem::take(&mut literal)));
+        }
+        parts.push(Part::Placeholder(placeholder));
+    }
+
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+
+    for required in &[Placeholder::File, Placeholder::Line] {
+        if !parts.contains(&Part::Placeholder(*required)) {
+            error!("The error format \"{}\" needs both a file (%f) and a line (%l)", pattern);
+            return Err(parser::Error::FormatError);
+        }
+    }
+
+    Ok(parts)
+}
+
+fn match_parts(parts: &[Part], line: &str, position: usize, captures: &mut Vec<(Placeholder, Range<usize>)>) -> bool {
+    let (part, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => return position == line.len(),
+    };
+
+    let remaining = &line[position..];
+
+    let candidates : Vec<usize> = match part {
+        Part::Literal(literal) => {
+            return remaining.starts_with(literal.as_str())
+                && match_parts(rest, line, position + literal.len(), captures);
+        },
+        // Numbers are greedy
+        Part::Placeholder(Placeholder::Line) | Part::Placeholder(Placeholder::Column) => {
+            let digits = remaining.bytes().take_while(u8::is_ascii_digit).count();
+            (1..=digits).rev().collect()
+        },
+        // The message is greedy, and everything else is lazy
+        Part::Placeholder(Placeholder::Message) => char_boundaries(remaining).into_iter().rev().collect(),
+        Part::Placeholder(Placeholder::File) => char_boundaries(remaining).into_iter().filter(|&end| end > 0).collect(),
+        Part::Placeholder(Placeholder::Skip) => char_boundaries(remaining),
+    };
+
+    let placeholder = match part {
+        Part::Placeholder(placeholder) => *placeholder,
+        Part::Literal(_) => unreachable!(),
+    };
+
+    for length in candidates {
+        captures.push((placeholder, position..position + length));
+        if match_parts(rest, line, position + length, captures) {
+            return true;
+        }
+        captures.pop();
+    }
+
+    false
+}
+
+fn char_boundaries(text: &str) -> Vec<usize> {
+    text.char_indices().map(|(index, _)| index).chain(Some(text.len())).collect()
+}
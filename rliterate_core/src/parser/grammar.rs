@@ -70,6 +70,20 @@ peg::parser!{grammar grammar() for str {
     rule css_file() -> &'input str
         = line_slice()
 
+    rule expansion_mode() -> ExpansionMode
+        = "repeat" { ExpansionMode::Repeat }
+        / "indent" { ExpansionMode::Indent }
+
+    rule line_ending() -> LineEnding
+        = "lf" { LineEnding::Lf }
+        / "crlf" { LineEnding::CrLf }
+        / "native" { LineEnding::native() }
+
+    rule toc_placement() -> TableOfContents
+        = "none" { TableOfContents::None }
+        / "top" { TableOfContents::Top }
+        / "sidebar" { TableOfContents::Sidebar }
+
     rule a_command() -> Command<'input>
         = "title" __ title:name() { Command::Title(title) }
         / esses:$("s"+) __ section_name:(name()?)
@@ -80,10 +94,20 @@ peg::parser!{grammar grammar() for str {
         / "line_numbers" __ pattern:line_num_pattern() { Command::LineNumbers(pattern) }
         / "compiler" __ sh_command:shell_command() { Command::Compiler(sh_command) }
         / "error_format" __ err_format:error_pattern() { Command::ErrorFormat(err_format) }
-        / "book" __ { Command::Book }
+        / "book" { Command::Book }
         / "add_css" __ file:css_file() { Command::AddCss(file) }
         / "overwrite_css" __ file:css_file() { Command::OverwriteCss(file) }
         / "colorscheme" __ file:css_file() { Command::Colorscheme(file) }
+        / "expansion" __ mode:expansion_mode() { Command::Expansion(mode) }
+        / "line_endings" __ ending:line_ending() { Command::LineEndings(ending) }
+        / "strip_trailing_whitespace" { Command::StripTrailingWhitespace }
+        / "final_newline" { Command::FinalNewline }
+        / "block_markers" { Command::BlockMarkers }
+        / "doc_comment_type" __ pattern:comment_pattern() { Command::DocCommentType(pattern) }
+        / "prose_comments" { Command::ProseComments }
+        / "formatter" __ sh_command:shell_command() { Command::Formatter(sh_command) }
+        / "toc" __ placement:toc_placement() { Command::Toc(placement) }
+        / "banner" text:(__ text:line_slice() { text })? { Command::Banner(text) }
         / expected!("A valid command")
 
     rule command() -> Command<'input>
@@ -114,6 +138,7 @@ peg::parser!{grammar grammar() for str {
         / "noTangle" { BlockModifier::NOTANGLE }
         / "noWeave" { BlockModifier::NOWEAVE }
         / "noHeader" { BlockModifier::NOHEADER}
+        / "docComment" { BlockModifier::DOCCOMMENT }
     rule append() -> BlockModifier
         = "+=" { BlockModifier::APPEND }
     rule redef() -> BlockModifier
@@ -135,9 +160,9 @@ peg::parser!{grammar grammar() for str {
     /// Chapter links
 
     rule chapter_file() -> &'input str
-        = $((!"(" [_])+)
+        = $((!")" [_])+)
     rule chapter() -> (&'input str, &'input str)
-        = "[" _ title:name() _ "](" filename:chapter_file() ")" { (title, filename) }
+        = "[" _ title:delimited_name(<"]">) _ "](" filename:chapter_file() ")" { (title, filename) }
 
     /// The whole file
 
@@ -174,6 +199,68 @@ pub enum Command<'a> {
     AddCss(&'a str),
     OverwriteCss(&'a str),
     Colorscheme(&'a str),
+    Expansion(ExpansionMode),
+    LineEndings(LineEnding),
+    StripTrailingWhitespace,
+    FinalNewline,
+    Banner(Option<&'a str>),
+    BlockMarkers,
+    DocCommentType(&'a str),
+    ProseComments,
+    Formatter(&'a str),
+    Toc(TableOfContents),
+}
+
+/// How a reference to a multi-line block is expanded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpansionMode {
+    /// Every expanded line gets all of the text before and after the reference
+    Repeat,
+    /// Only the indentation carries forward - the text before and after the reference is
+    /// emitted once, on the first and last expanded lines
+    Indent,
+}
+
+/// Where the table of contents goes in the weave
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TableOfContents {
+    None,
+    Top,
+    /// Beside the document, in the HTML weave
+    Sidebar,
+}
+
+/// The line ending used in tangled output
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// The usual line ending of the platform that rliterate is running on
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    /// Find the line ending that some text uses, going by its first line
+    pub fn detect(text: &str) -> Self {
+        match text.find('\n') {
+            Some(index) if text[..index].ends_with('\r') => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
 }
 
 bitflags! {
@@ -183,6 +270,7 @@ bitflags! {
         const NOTANGLE = 0b00000100;
         const NOWEAVE  = 0b00001000;
         const NOHEADER = 0b00010000;
+        const DOCCOMMENT = 0b00100000;
     }
 }
 
@@ -198,11 +286,12 @@ pub enum LitLine<'a> {
     Prose(&'a str),
 }
 
-fn partial_lines<'a>(input: &'a str) -> Result<Vec<PartialLitLine<'a>>, ParseError> {
+fn partial_lines<'a>(input: &'a str) -> Result<Vec<(usize, PartialLitLine<'a>)>, ParseError> {
     let mut partial_lines = vec![];
 
-    for line in input.lines() {
-        partial_lines.push(partial_line(line)?);
+    // Line numbers are 1-based, to match what editors and compilers report
+    for (index, line) in input.lines().enumerate() {
+        partial_lines.push((index + 1, partial_line(line)?));
     }
 
     Ok(partial_lines)
@@ -213,33 +302,37 @@ pub fn lit_file<'a>(input: &'a str) -> Result<Vec<LitBlock<'a>>, ParseError> {
     parse_lines(partial_lines)
 }
 
-fn parse_lines<'a>(lines: Vec<PartialLitLine<'a>>) -> Result<Vec<LitBlock<'a>>, ParseError> {
+fn parse_lines<'a>(lines: Vec<(usize, PartialLitLine<'a>)>) -> Result<Vec<LitBlock<'a>>, ParseError> {
     let mut blocks = vec![];
 
+    let mut prose_start = 1;
     let mut prose_lines: Vec<&'a str> = vec![];
    
     macro_rules! close_prose {
-        () => {
-            blocks.push(LitBlock::Prose(prose_lines));
+        ($next_line:expr) => {
+            blocks.push(LitBlock::Prose { first_line: prose_start, lines: prose_lines });
+            prose_start = $next_line;
             prose_lines = vec![];
         }
     }
 
     let mut line_iter = lines.into_iter();
             
-    while let Some(line) = line_iter.next() {
+    while let Some((line_number, line)) = line_iter.next() {
         match line {
             PartialLitLine::CodeBlockStart(name, modifiers) => {
-                close_prose!();
+                close_prose!(line_number + 1);
 
                 let mut code = CodeBlock {
                         block_name: name,
                         modifiers: modifiers,
+                        first_line: line_number + 1,
                         contents: vec![],
                 };
-                while let Some(code_line) = line_iter.next() {
+                while let Some((code_line_number, code_line)) = line_iter.next() {
                     match code_line {
                         PartialLitLine::CodeBlockEnd => {
+                            prose_start = code_line_number + 1;
                             break;
                         },
                         PartialLitLine::Line(line) => {
@@ -263,11 +356,11 @@ fn parse_lines<'a>(lines: Vec<PartialLitLine<'a>>) -> Result<Vec<LitBlock<'a>>,
 
                 match line {
                     LitLine::Command(command) => {
-                        close_prose!();
+                        close_prose!(line_number + 1);
                         blocks.push(LitBlock::Command(command));
                     },
                     LitLine::Chapter { title, file_name } => {
-                        close_prose!();
+                        close_prose!(line_number + 1);
                         blocks.push(LitBlock::Chapter { title, file_name });
                     },
                     LitLine::Prose(line) => {
@@ -278,12 +371,20 @@ fn parse_lines<'a>(lines: Vec<PartialLitLine<'a>>) -> Result<Vec<LitBlock<'a>>,
         }
     }
 
+    // Prose is only closed by what follows it, so the prose at the end of a file has to be closed
+    // here, or it would be lost
+    if !prose_lines.is_empty() {
+        blocks.push(LitBlock::Prose { first_line: prose_start, lines: prose_lines });
+    }
+
     Ok(blocks)
 }
 
 pub struct CodeBlock<'a> {
     pub block_name: &'a str,
     pub modifiers: BlockModifier,
+    /// The line number of the first line of the contents
+    pub first_line: usize,
     pub contents: Vec<&'a str>
 }
 
@@ -291,6 +392,6 @@ pub enum LitBlock<'a> {
     Command(Command<'a>),
     Chapter { title: &'a str, file_name: &'a str },
     Code(CodeBlock<'a>),
-    Prose(Vec<&'a str>),
+    Prose { first_line: usize, lines: Vec<&'a str> },
 }
 
@@ -21,9 +21,11 @@
 
 #[macro_use]
 mod ast;
-pub use self::ast::{FormatFn, LitFile, Metadata, Section, Block, CompilerSettings, generate_line_number_format};
+pub use self::ast::{FormatFn, LitFile, Metadata, Section, Block, BookStatus, CompilerSettings, DEFAULT_BANNER, generate_line_number_format};
 mod grammar;
-pub use self::grammar::{BlockModifier};
+pub use self::grammar::{BlockModifier, ExpansionMode, LineEnding, TableOfContents};
+mod error_format;
+pub use self::error_format::{ErrorFormat, CompilerMessage};
 
 use input;
 use output::css;
@@ -47,6 +49,7 @@ pub enum Error {
     GrammarError(grammar::ParseError),
     FormatError,
     BadChapterPath,
+    BadEncoding(PathBuf),
 }
 
 impl From<grammar::ParseError> for Error {
@@ -87,6 +90,10 @@ impl ParseState {
             //  * the input files are all under the input directory
             //  * the input files all have some parent
             let relative_directory = file.strip_prefix(&input_settings.input_path).unwrap().parent().unwrap();
+            if parse_state.file_map.contains_key(&file) {
+                trace!("\"{}\" was already loaded as a chapter of a book", file.to_string_lossy());
+                continue;
+            }
             parse_state.parse_file(&file, relative_directory)?;
         }
 
@@ -102,13 +109,14 @@ impl ParseState {
             Err(Error::FileRepeat)
         } else { 
             trace!("Parsing file \"{}\"", file_path.to_string_lossy());
-            let file_contents = fs::read_to_string(file_path)?; 
+            let file_contents = read_lit_file(file_path)?;
             let lit_blocks = grammar::lit_file(&file_contents)?;
             
             self.in_progress.insert(file_path.clone());
 
-            let (mut lit_file, settings) = LitFile::parse(self, lit_blocks)?;
+            let (mut lit_file, settings) = LitFile::parse(self, file_path, relative_directory, lit_blocks)?;
             lit_file.metadata.relative_directory.push(relative_directory);
+            lit_file.metadata.source_line_ending = LineEnding::detect(&file_contents);
 
             if !settings.is_default() {
                 once!(self.css_settings, is_some, Some(settings), Error::ConflictingCss);
@@ -120,6 +128,47 @@ impl ParseState {
             Ok(())
         }
     }
+
+    /// Load a chapter of a book, unless it has already been loaded as an input file
+    pub fn parse_chapter(&mut self, file_path: &Path, relative_directory: &Path) -> Result<()> {
+        if self.file_map.contains_key(file_path) {
+            trace!("\"{}\" was already loaded", file_path.to_string_lossy());
+            Ok(())
+        } else {
+            self.parse_file(&file_path.to_path_buf(), relative_directory)
+        }
+    }
+}
+
+/// Read a .lit file, which must be UTF-8. A leading byte order mark is dropped.
+fn read_lit_file(file_path: &Path) -> Result<String> {
+    const UTF8_BOM : &[u8] = b"\xEF\xBB\xBF";
+    const UTF16_BOMS : &[&[u8]] = &[b"\xFF\xFE", b"\xFE\xFF"];
+
+    let mut bytes = fs::read(file_path)?;
+
+    if bytes.starts_with(UTF8_BOM) {
+        debug!("Dropping the byte order mark from \"{}\"", file_path.to_string_lossy());
+        bytes.drain(..UTF8_BOM.len());
+    } else if UTF16_BOMS.iter().any(|bom| bytes.starts_with(bom)) {
+        error!("\"{}\" is encoded as UTF-16, but .lit files must be UTF-8", file_path.to_string_lossy());
+        return Err(Error::BadEncoding(file_path.to_path_buf()));
+    }
+
+    String::from_utf8(bytes).map_err(|err| {
+        let (line, column) = position_of(&err.as_bytes()[..err.utf8_error().valid_up_to()]);
+
+        error!("\"{}\" is not valid UTF-8 - the first invalid byte is at line {}, byte {} - .lit files must be UTF-8",
+               file_path.to_string_lossy(), line, column);
+        Error::BadEncoding(file_path.to_path_buf())
+    })
+}
+
+/// The line and byte column just after some text, both counting from 1
+fn position_of(text: &[u8]) -> (usize, usize) {
+    let line = text.iter().filter(|&&byte| byte == b'\n').count() + 1;
+    let column = text.iter().rev().take_while(|&&byte| byte != b'\n').count() + 1;
+    (line, column)
 }
 
 fn get_input_files(input_path: &Path, recurse: bool) -> Result<Vec<PathBuf>> {
@@ -164,3 +213,20 @@ pub fn get_input_file(input_path: &Path) -> Result<PathBuf> {
         Err(Error::FileSystem(io::Error::new(io::ErrorKind::Other, format!("Input path was not a file: {}", input_path.to_string_lossy()))))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{position_of};
+
+    use std::fs;
+    use std::path::{Path};
+
+    #[test]
+    fn invalid_bytes_are_found_by_line_and_byte() {
+        let bytes = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/invalid_utf8/main.lit")).unwrap();
+        let err = String::from_utf8(bytes).unwrap_err();
+
+        // The "é" before the bad byte takes two bytes, so the column counts bytes rather than characters
+        assert_eq!(position_of(&err.as_bytes()[..err.utf8_error().valid_up_to()]), (6, 12));
+    }
+}
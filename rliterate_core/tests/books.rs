@@ -0,0 +1,93 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+mod common;
+
+use rliterate_core::input::InputSettings;
+use rliterate_core::output::weave::{Type};
+use rliterate_core::parser::{BookStatus, ParseState};
+
+/// The links in the navigation at the top of a woven page
+fn navigation(out_dir: &rliterate_core::output::ScratchDir, page: &str) -> String {
+    let html = common::read(out_dir, page);
+    let start = html.find("<nav class=\"chapter-nav\">\n").expect("The page has no navigation");
+    let end = start + html[start..].find("</nav>").unwrap();
+    html[start..end].lines().nth(1).unwrap().to_owned()
+}
+
+#[test]
+fn chapter_links_are_relative_to_their_book() {
+    let fixtures = common::fixtures("books");
+    let parse_state = ParseState::from_input(InputSettings { input_path: fixtures.clone(), recurse: false }).unwrap();
+
+    let chapters = |book: &str| match parse_state.file_map[&fixtures.join(book)].book_status {
+        BookStatus::IsBook(ref chapters) => chapters.clone(),
+        BookStatus::NotBook => panic!("{} isn't a book", book),
+    };
+
+    assert_eq!(chapters("book.lit"), vec![fixtures.join("parts/start.lit"), fixtures.join("middle.lit")]);
+    assert_eq!(chapters("parts/start.lit"), vec![fixtures.join("parts/deep/end.lit")]);
+}
+
+#[test]
+fn chapters_are_woven_relative_to_their_book() {
+    let out_dir = common::weave("books", Type::HtmlViaMarkdown(None));
+
+    for page in &["book.html", "middle.html", "parts/start.html", "parts/deep/end.html"] {
+        assert!(out_dir.path().join(page).is_file(), "{} wasn't woven", page);
+    }
+}
+
+#[test]
+fn chapters_take_their_titles_from_the_link() {
+    let out_dir = common::weave("books", Type::HtmlViaMarkdown(None));
+
+    assert!(common::read(&out_dir, "parts/start.html").contains("<h1>Getting started (part 1)</h1>"));
+}
+
+#[test]
+fn chapters_link_to_their_neighbours_and_their_book() {
+    let out_dir = common::weave("books", Type::HtmlViaMarkdown(None));
+
+    assert_eq!(navigation(&out_dir, "parts/start.html"),
+               "<p><a href=\"../book.html\">↑ Book</a> | <a href=\"../middle.html\">Middle →</a></p>");
+    assert_eq!(navigation(&out_dir, "middle.html"),
+               "<p><a href=\"parts/start.html\">← Getting started (part 1)</a> | <a href=\"book.html\">↑ Book</a></p>");
+    // A chapter of a chapter is in a book of its own
+    assert_eq!(navigation(&out_dir, "parts/deep/end.html"),
+               "<p><a href=\"../start.html\">↑ Getting started (part 1)</a></p>");
+}
+
+#[test]
+fn books_end_with_an_index_of_their_chapters() {
+    let out_dir = common::weave("books", Type::HtmlViaMarkdown(None));
+
+    let book = common::read(&out_dir, "book.html");
+    assert!(!book.contains("chapter-nav"));
+    assert!(book.contains("<p><a href=\"parts/start.html\">Getting started (part 1)</a></p>"));
+    assert!(book.contains("<p><a href=\"parts/start.html#first-steps\">1 First steps</a></p>"));
+    assert!(book.contains("<p><a href=\"middle.html#in-the-middle\">1 In the middle</a></p>"));
+
+    let nested_book = common::read(&out_dir, "parts/start.html");
+    assert!(nested_book.contains("<p><a href=\"deep/end.html#the-last-section\">1 The last section</a></p>"));
+}
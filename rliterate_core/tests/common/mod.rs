@@ -0,0 +1,70 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Helpers shared by the integration tests. Each test crate only uses some of them.
+#![allow(dead_code)]
+
+use rliterate_core;
+use rliterate_core::input::InputSettings;
+use rliterate_core::output::{self, ScratchDir};
+use rliterate_core::output::weave::{Type};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A directory of fixtures under `tests/fixtures`
+pub fn fixtures(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+/// Weave a directory of fixtures into a fresh scratch directory, which is deleted when dropped
+pub fn weave(fixtures_name: &str, weave_type: Type) -> ScratchDir {
+    weave_with(fixtures_name, weave_type, |_| {})
+}
+
+/// Weave a directory of fixtures, with some of the settings changed from their defaults first
+pub fn weave_with<F>(fixtures_name: &str, weave_type: Type, change: F) -> ScratchDir
+    where F: FnOnce(&mut output::weave::Globals) {
+    let out_dir = ScratchDir::new("test").unwrap();
+
+    let mut weave_settings = output::weave::Globals {
+        weave_type,
+        out_dir: out_dir.path().to_path_buf(),
+        css: output::css::Globals::default(),
+        toc: None,
+        single_page: false,
+    };
+    change(&mut weave_settings);
+
+    let output_settings = output::Globals {
+        generate_output: true,
+        weave: Some(weave_settings),
+        tangle: None,
+    };
+    rliterate_core::run(InputSettings { input_path: fixtures(fixtures_name), recurse: false }, output_settings).unwrap();
+
+    out_dir
+}
+
+/// Read a file written into a scratch directory
+pub fn read(out_dir: &ScratchDir, path: &str) -> String {
+    fs::read_to_string(out_dir.path().join(path)).unwrap()
+}
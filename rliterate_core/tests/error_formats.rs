@@ -0,0 +1,114 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+use rliterate_core::parser::ErrorFormat;
+
+/// The file, line and column that the format picks out of each line of output that it matches
+fn locations<'o>(error_format: &ErrorFormat, output: &'o str) -> Vec<(&'o str, &'o str, Option<&'o str>)> {
+    output.lines()
+        .filter_map(|line| {
+            let message = error_format.match_line(line)?;
+            Some((&line[message.file], &line[message.line], message.column.map(|column| &line[column])))
+        })
+        .collect()
+}
+
+#[test]
+fn rustc_preset() {
+    let error_format = ErrorFormat::preset("rustc").unwrap();
+
+    assert_eq!(locations(&error_format, include_str!("fixtures/error_formats/rustc.txt")), vec![
+        ("main.rs", "2", Some("17")),
+        ("main.rs", "3", Some("5")),
+    ]);
+}
+
+#[test]
+fn gcc_preset() {
+    let error_format = ErrorFormat::preset("gcc").unwrap();
+
+    assert_eq!(locations(&error_format, include_str!("fixtures/error_formats/gcc.txt")), vec![
+        ("main.c", "3", Some("13")),
+        ("main.c", "4", Some("12")),
+        ("main.c", "4", Some("12")),
+        ("main.c", "3", Some("9")),
+    ]);
+}
+
+#[test]
+fn clang_preset() {
+    let error_format = ErrorFormat::preset("clang").unwrap();
+
+    assert_eq!(locations(&error_format, include_str!("fixtures/error_formats/clang.txt")), vec![
+        ("main.c", "3", Some("9")),
+        ("main.c", "4", Some("12")),
+    ]);
+}
+
+#[test]
+fn go_preset() {
+    let error_format = ErrorFormat::preset("go").unwrap();
+
+    assert_eq!(locations(&error_format, include_str!("fixtures/error_formats/go.txt")), vec![
+        ("./main.go", "4", Some("2")),
+        ("./main.go", "5", Some("2")),
+    ]);
+}
+
+#[test]
+fn javac_preset() {
+    let error_format = ErrorFormat::preset("javac").unwrap();
+
+    assert_eq!(locations(&error_format, include_str!("fixtures/error_formats/javac.txt")), vec![
+        ("Main.java", "3", None),
+        ("Main.java", "4", None),
+    ]);
+}
+
+#[test]
+fn presets_are_guessed_from_the_compiler_command() {
+    let guessed = |command| {
+        let error_format = ErrorFormat::guess(command)?;
+        locations(&error_format, include_str!("fixtures/error_formats/rustc.txt")).first().cloned()
+    };
+
+    assert_eq!(guessed("rustc --edition 2018 src/main.rs"), Some(("main.rs", "2", Some("17"))));
+    assert_eq!(guessed("cd build && /usr/bin/cargo build"), Some(("main.rs", "2", Some("17"))));
+    assert!(ErrorFormat::guess("gcc-12 -Wall main.c").is_some());
+    assert!(ErrorFormat::guess("javac Main.java").is_some());
+    assert!(ErrorFormat::guess("make all").is_none());
+}
+
+#[test]
+fn custom_formats_use_the_literate_placeholders() {
+    let error_format = ErrorFormat::parse("%f\\(%l\\):%s: %m").unwrap();
+    let line = "main.d(12): Error: undefined identifier `x`";
+    let message = error_format.match_line(line).unwrap();
+
+    assert_eq!(&line[message.file], "main.d");
+    assert_eq!(&line[message.line], "12");
+    assert_eq!(&line[message.message.unwrap()], "undefined identifier `x`");
+
+    assert!(ErrorFormat::parse("%f:%q").is_err());
+    assert!(ErrorFormat::parse("%m").is_err());
+}
@@ -0,0 +1,77 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+use rliterate_core::input::InputSettings;
+use rliterate_core::output::{self, tangle};
+
+use std::collections::hash_map::{RandomState};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path};
+
+/// Tangle the fixture into a fresh directory, and read back one of the files
+fn tangle(name: &str) -> String {
+    // The standard library seeds every `RandomState` randomly, which makes for a fresh name
+    let suffix = RandomState::new().build_hasher().finish();
+    let out_dir = std::env::temp_dir().join(format!("rliterate-expansion-{:016x}", suffix));
+    fs::create_dir(&out_dir).unwrap();
+
+    let output_settings = output::Globals {
+        generate_output: true,
+        weave: None,
+        tangle: Some(tangle::Globals {
+            out_dir: out_dir.clone(),
+            ..Default::default()
+        }),
+    };
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/expansion");
+    let result = rliterate_core::run(InputSettings { input_path: fixtures, recurse: false }, output_settings);
+
+    let contents = fs::read_to_string(out_dir.join(name));
+    fs::remove_dir_all(&out_dir).unwrap();
+    result.unwrap();
+    contents.unwrap()
+}
+
+#[test]
+fn indent_mode_puts_headers_on_their_own_lines() {
+    assert_eq!(tangle("indent.rs"), "// indent.rs\n\
+                                     fn main() {\n    \
+                                         // expr\n    \
+                                         let x = 1 +\n    \
+                                         2;\n    \
+                                         // args\n    \
+                                         call(a,\n    \
+                                         b, last);\n\
+                                     }\n");
+}
+
+#[test]
+fn repeat_mode_repeats_the_text_around_every_line() {
+    assert_eq!(tangle("repeat.rs"), "// repeat.rs\n\
+                                     fn main() {\n    \
+                                         let x = // expr;\n    \
+                                         let x = 1 +;\n    \
+                                         let x = 2;\n\
+                                     }\n");
+}
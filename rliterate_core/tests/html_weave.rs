@@ -0,0 +1,48 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+mod common;
+
+use rliterate_core::output::weave::{Type};
+
+/// Weave the prose fixture to HTML, and read it back
+fn weave() -> String {
+    let out_dir = common::weave("prose", Type::HtmlViaMarkdown(None));
+    common::read(&out_dir, "prose.html")
+}
+
+#[test]
+fn table_of_contents_style_is_not_escaped() {
+    let html = weave();
+
+    assert!(html.contains("<style>nav.toc ul{list-style-type:none;padding-left:1.5em}nav.toc>ul{padding-left:0}"));
+    assert!(!html.contains("nav.toc&gt;ul"));
+}
+
+#[test]
+fn default_colorscheme_is_not_escaped() {
+    let html = weave();
+    let colorscheme = include_str!("../src/output/weave/colorscheme.css");
+
+    assert!(html.contains(&format!("<style>{}</style>", colorscheme)));
+}
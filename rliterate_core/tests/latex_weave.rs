@@ -0,0 +1,76 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+mod common;
+
+use rliterate_core::output::weave::{CodeEnvironment, Type};
+
+use std::fs;
+
+/// Weave the fixtures, and read back the document woven from `paper.lit`
+fn weave(environment: CodeEnvironment) -> String {
+    let out_dir = common::weave("latex", Type::Latex(environment));
+    common::read(&out_dir, "paper.tex")
+}
+
+fn fixture(name: &str) -> String {
+    fs::read_to_string(common::fixtures("latex").join(name)).unwrap()
+}
+
+#[test]
+fn listings_document() {
+    assert_eq!(weave(CodeEnvironment::Listings), fixture("paper.tex"));
+}
+
+#[test]
+fn minted_captions_go_on_a_listing() {
+    let latex = weave(CodeEnvironment::Minted);
+
+    assert!(latex.contains("\\usepackage{minted}\n"));
+    assert!(!latex.contains("lstlisting"));
+    assert!(latex.contains("\\begin{listing}[H]\n\\caption{\\{the body\\} +=}\n\\begin{minted}{rust}\nprintln!(\"{}\", total);\n\\end{minted}\n\\end{listing}\n"));
+    assert!(latex.contains("\\begin{minted}{bash}\necho \"hi\"\n\\end{minted}\n"));
+    // Blocks without a header have no caption, so no float either
+    assert!(latex.contains("\\phantomsection\\label{bare}\n\\begin{minted}{rust}\nbare\n\\end{minted}\n\n"));
+}
+
+#[test]
+fn math_is_passed_through_untouched() {
+    let latex = weave(CodeEnvironment::Listings);
+
+    assert!(latex.contains("with $x^2$ math."));
+    assert!(latex.contains("We compute $a_1 + b_1$ and $$\\sum_{i=1}^{n} i = \\frac{n(n+1)}{2}$$"));
+    assert!(latex.contains("and \\(x*y*z\\) inline"));
+    // Dollars which aren't math, and dollars in code, are escaped
+    assert!(latex.contains("costing \\$5 and \\$10 - 50\\% off\\_here."));
+    assert!(latex.contains("\\texttt{code with \\$dollars\\$}"));
+}
+
+#[test]
+fn cross_references_are_refs() {
+    let latex = weave(CodeEnvironment::Listings);
+
+    assert!(latex.contains("\\section{The body}\\label{the-body}\n"));
+    assert!(latex.contains("\\emph{Used in \\S\\ref{introduction}.}\\\\\n\\emph{Added to in \\S\\ref{the-body}.}\\\\\n\\emph{Redefined in \\S\\ref{details}.}\n"));
+    assert!(latex.contains("See \\hyperref[the-body-2]{\\{the body\\}}"));
+}
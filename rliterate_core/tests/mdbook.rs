@@ -0,0 +1,63 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+mod common;
+
+use rliterate_core::output::{ScratchDir};
+use rliterate_core::output::weave::{Type};
+
+/// Weave the fixture book
+fn weave() -> ScratchDir {
+    common::weave("mdbook", Type::MdBook)
+}
+
+#[test]
+fn summary_lists_the_chapters_in_order() {
+    let summary = common::read(&weave(), "src/SUMMARY.md");
+
+    assert_eq!(summary, "# Summary\n\n\
+                         [The \\\"Literate\\\" Book](book.md)\n\n\
+                         - [Getting started](start.md)\n\
+                         - [Parts](parts/parts.md)\n    \
+                             - [A deeper part](parts/deeper.md)\n");
+}
+
+#[test]
+fn config_takes_the_book_title() {
+    let config = common::read(&weave(), "book.toml");
+
+    assert_eq!(config, "[book]\ntitle = \"The \\\"Literate\\\" Book\"\nsrc = \"src\"\n");
+}
+
+#[test]
+fn chapters_are_markdown_pages_without_navigation() {
+    let out_dir = weave();
+    let start = common::read(&out_dir, "src/start.md");
+    let book = common::read(&out_dir, "src/book.md");
+
+    assert!(out_dir.path().join("src/parts/deeper.md").is_file());
+    assert!(start.starts_with("# Getting started\n"));
+    assert!(start.contains("<a id=\"body\"></a>**{body}**\n\n```rust\nprintln!(\"started\");\n```"));
+    assert!(start.contains("*Used in [§1](#setup \"Setup\").*"));
+    assert!(!book.contains("Chapters"));
+}
@@ -0,0 +1,62 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+use rliterate_core::input::InputSettings;
+use rliterate_core::parser::{self, Block, ParseState};
+
+use std::path::{Path};
+
+fn parse(name: &str) -> ParseState {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    ParseState::from_input(InputSettings { input_path: fixtures, recurse: false }).unwrap()
+}
+
+#[test]
+fn prose_at_the_end_of_a_file_is_kept() {
+    let parse_state = parse("trailing_prose");
+    let file = parse_state.file_map.values().next().unwrap();
+
+    match file.sections.last().and_then(|section| section.blocks.last()) {
+        Some(Block::Prose { lines, .. }) => assert_eq!(lines, &["", "The last words of the file,", "after its last code block."]),
+        _ => panic!("The prose at the end of the file was lost"),
+    }
+}
+
+#[test]
+fn byte_order_marks_are_dropped() {
+    let parse_state = parse("bom");
+    let file = parse_state.file_map.values().next().unwrap();
+
+    assert_eq!(file.title, "Byte order mark");
+}
+
+#[test]
+fn invalid_utf8_is_rejected() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/invalid_utf8");
+
+    match ParseState::from_input(InputSettings { input_path: fixtures.clone(), recurse: false }) {
+        Err(parser::Error::BadEncoding(path)) => assert_eq!(path, fixtures.join("main.lit")),
+        Err(err) => panic!("expected a bad encoding, not {:?}", err),
+        Ok(_) => panic!("expected a bad encoding"),
+    }
+}
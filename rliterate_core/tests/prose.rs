@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+mod common;
+
+use rliterate_core::output::weave::{Type};
+use rliterate_core::parser::{TableOfContents};
+
+/// Weave the fixture without a table of contents, and read it back
+fn weave(weave_type: Type) -> String {
+    let woven = format!("prose.{}", weave_type.extension());
+    let out_dir = common::weave_with("prose", weave_type, |settings| settings.toc = Some(TableOfContents::None));
+    common::read(&out_dir, &woven)
+}
+
+#[test]
+fn lists_span_lines() {
+    let html = weave(Type::HtmlViaMarkdown(None));
+
+    assert_eq!(html.matches("<ul>").count(), 1);
+    assert_eq!(html.matches("<li>").count(), 5);
+    assert!(html.contains("second,\nwhich goes on</p>"));
+    assert!(html.contains("<ol>\n<li>one</li>\n<li>two</li>\n</ol>"));
+}
+
+#[test]
+fn reference_links_are_resolved() {
+    let html = weave(Type::HtmlViaMarkdown(None));
+
+    assert!(html.contains("<a href=\"guide.html\" title=\"The guide\">guide</a>"));
+    assert!(!html.contains("[docs]"));
+}
+
+#[test]
+fn emphasis_spans_lines() {
+    let html = weave(Type::HtmlViaMarkdown(None));
+    assert!(html.contains("<em>emphasis\nacross lines</em>, and <strong>strong\ntext</strong>."));
+
+    let markdown = weave(Type::Markdown);
+    assert!(markdown.contains("*emphasis across lines*, and **strong text**."));
+}
+
+#[test]
+fn block_references_in_paragraphs_are_links() {
+    let html = weave(Type::HtmlViaMarkdown(None));
+
+    assert!(html.contains("<p>The <a href=\"#body\">{body}</a> block, in a paragraph\nthat spans lines.</p>"));
+}
+
+#[test]
+fn tables_are_parsed() {
+    let html = weave(Type::HtmlViaMarkdown(None));
+    assert!(html.contains("<table><thead><tr><th align=\"left\"> Mode </th><th align=\"right\"> Output </th></tr></thead>"));
+    assert!(html.contains("<tr><td align=\"left\"> weave </td><td align=\"right\"> <em>documents</em> </td></tr>"));
+
+    let markdown = weave(Type::Markdown);
+    assert!(markdown.contains("| Mode | Output |\n| :-- | --: |\n| tangle | code |\n| weave | *documents* |"));
+}
@@ -0,0 +1,122 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+mod common;
+
+use rliterate_core::input::InputSettings;
+use rliterate_core::output::{self, tangle, ScratchDir};
+
+use std::fs;
+
+const HAND_WRITTEN : &str = "fn main() {
+    // lit:begin args
+    let name = None;
+    // lit:end
+
+    // lit:begin other
+    let kept = true;
+    // lit:end
+
+    // lit:begin greeting
+    // lit:end
+}
+";
+
+/// Tangle the fixture into a scratch directory holding a hand-written `main.rs`
+fn tangle(hand_written: &str) -> (ScratchDir, rliterate_core::Result<()>) {
+    let out_dir = ScratchDir::new("test").unwrap();
+    fs::write(out_dir.path().join("main.rs"), hand_written).unwrap();
+
+    let output_settings = output::Globals {
+        generate_output: true,
+        weave: None,
+        tangle: Some(tangle::Globals {
+            out_dir: out_dir.path().to_path_buf(),
+            ..Default::default()
+        }),
+    };
+    let result = rliterate_core::run(InputSettings { input_path: common::fixtures("regions"), recurse: false }, output_settings);
+
+    (out_dir, result)
+}
+
+fn is_region_error(result: rliterate_core::Result<()>, expected: &str, duplicate: bool) -> bool {
+    match result {
+        Err(rliterate_core::Error::Output(output::Error::MissingRegion(_, ref region))) => !duplicate && region == expected,
+        Err(rliterate_core::Error::Output(output::Error::DuplicateRegion(_, ref region))) => duplicate && region == expected,
+        _ => false,
+    }
+}
+
+#[test]
+fn regions_are_replaced() {
+    let (out_dir, result) = tangle(HAND_WRITTEN);
+    result.unwrap();
+
+    assert_eq!(common::read(&out_dir, "main.rs"), "fn main() {
+    // lit:begin args
+    let name = std::env::args().nth(1);
+    // lit:end
+
+    // lit:begin other
+    let kept = true;
+    // lit:end
+
+    // lit:begin greeting
+    println!(\"Hello, {:?}\", name);
+    // lit:end
+}
+");
+}
+
+#[test]
+fn a_hash_without_braces_is_part_of_a_file_name() {
+    let (out_dir, result) = tangle(HAND_WRITTEN);
+    result.unwrap();
+
+    assert_eq!(common::read(&out_dir, "C#/Program.cs"), "class Program {}\n");
+}
+
+#[test]
+fn a_region_without_an_end_is_an_error() {
+    let (out_dir, result) = tangle(&HAND_WRITTEN.replacen("    // lit:end\n}", "}", 1));
+
+    assert!(is_region_error(result, "greeting", false));
+    assert_eq!(common::read(&out_dir, "main.rs"), HAND_WRITTEN.replacen("    // lit:end\n}", "}", 1));
+}
+
+#[test]
+fn a_missing_region_is_an_error() {
+    let (out_dir, result) = tangle(&HAND_WRITTEN.replace("lit:begin greeting", "lit:begin farewell"));
+
+    assert!(is_region_error(result, "greeting", false));
+    assert!(common::read(&out_dir, "main.rs").contains("let name = None;"));
+}
+
+#[test]
+fn a_region_used_twice_is_an_error() {
+    let (out_dir, result) = tangle(&HAND_WRITTEN.replace("lit:begin other", "lit:begin args"));
+
+    assert!(is_region_error(result, "args", true));
+    assert!(common::read(&out_dir, "main.rs").contains("let name = None;"));
+}
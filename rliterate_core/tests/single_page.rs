@@ -0,0 +1,36 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+mod common;
+
+use rliterate_core::output::weave::{Type};
+
+#[test]
+fn links_between_chapters_stay_in_the_page() {
+    let out_dir = common::weave_with("single_page", Type::HtmlViaMarkdown(None), |settings| settings.single_page = true);
+    let html = common::read(&out_dir, "book.html");
+
+    // The second intro's anchor is numbered, as the first one took its name
+    assert!(html.contains("See <a href=\"#intro-2\">the intro to two</a>, <a href=\"#two\">two</a>, and <a href=\"#intro\">this intro</a>."));
+    assert!(html.contains("Back to <a href=\"#intro\">the intro to one</a>, <a href=\"#\">the book</a> and <a href=\"#a-rs\">a.rs</a>."));
+}
@@ -0,0 +1,128 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate rliterate_core;
+
+use rliterate_core::input::InputSettings;
+use rliterate_core::output::{self, tangle};
+
+use std::collections::hash_map::{RandomState};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+
+/// A fresh tangle of a copy of the fixture, which can be edited and untangled, and is deleted
+/// when dropped
+struct Untangle {
+    dir: PathBuf,
+}
+
+impl Untangle {
+    fn new() -> Self {
+        // The standard library seeds every `RandomState` randomly, which makes for a fresh name
+        let suffix = RandomState::new().build_hasher().finish();
+        let dir = std::env::temp_dir().join(format!("rliterate-untangle-{:016x}", suffix));
+        fs::create_dir(&dir).unwrap();
+        fs::create_dir(dir.join("lit")).unwrap();
+
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/untangle/main.lit");
+        fs::copy(fixture, dir.join("lit/main.lit")).unwrap();
+
+        let untangle = Untangle { dir };
+        untangle.run(false).unwrap();
+        untangle
+    }
+
+    fn run(&self, untangle: bool) -> rliterate_core::Result<()> {
+        let output_settings = output::Globals {
+            generate_output: true,
+            weave: None,
+            tangle: Some(tangle::Globals {
+                out_dir: self.dir.join("out"),
+                untangle,
+                ..Default::default()
+            }),
+        };
+        rliterate_core::run(InputSettings { input_path: self.dir.join("lit"), recurse: false }, output_settings)
+    }
+
+    /// Change one of the tangled files
+    fn edit(&self, name: &str, from: &str, to: &str) {
+        let path = self.dir.join("out").join(name);
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains(from));
+        fs::write(&path, contents.replacen(from, to, 1)).unwrap();
+    }
+
+    fn untangle(&self) -> rliterate_core::Result<()> {
+        self.run(true)
+    }
+
+    fn lit(&self) -> String {
+        fs::read_to_string(self.dir.join("lit/main.lit")).unwrap()
+    }
+}
+
+impl Drop for Untangle {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn edits_are_copied_back() {
+    let untangle = Untangle::new();
+    untangle.edit("main.rs", "    let b = 2;\n", "    let b = 20;\n");
+    untangle.untangle().unwrap();
+
+    assert!(untangle.lit().contains("--- body\nlet a = 1;\nlet b = 20;\nlet c = 3;\n---\n"));
+}
+
+#[test]
+fn insertions_are_copied_back() {
+    let untangle = Untangle::new();
+    untangle.edit("main.rs", "    let b = 2;\n", "    let b = 2;\n    let d = 4;\n");
+    untangle.untangle().unwrap();
+
+    assert!(untangle.lit().contains("--- body\nlet a = 1;\nlet b = 2;\nlet d = 4;\nlet c = 3;\n---\n"));
+}
+
+#[test]
+fn deletions_are_copied_back() {
+    let untangle = Untangle::new();
+    untangle.edit("main.rs", "    let a = 1;\n", "");
+    untangle.untangle().unwrap();
+
+    assert!(untangle.lit().contains("--- body\nlet b = 2;\nlet c = 3;\n---\n"));
+}
+
+#[test]
+fn edits_to_a_block_used_twice_conflict() {
+    let untangle = Untangle::new();
+    let original = untangle.lit();
+    untangle.edit("twice.rs", "let shared = 0;\n", "let shared = 1;\n");
+
+    match untangle.untangle() {
+        Err(rliterate_core::Error::Output(output::Error::UntangleConflicts(conflicts))) => assert_eq!(conflicts, 1),
+        result => panic!("expected a conflict, not {:?}", result),
+    }
+    assert_eq!(untangle.lit(), original);
+}
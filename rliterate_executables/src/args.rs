@@ -19,7 +19,7 @@
  * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
  */
 
-use clap::{App, Arg, ArgGroup, ArgMatches};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
 
 use std::path;
 
@@ -54,7 +54,15 @@ pub fn get_cargo_arg_parser() -> App<'static, 'static> {
     let app = App::new("cargo lit")
         .about("A cargo subcommand for processing a literate project's `.lit` files and generating source code.")
         .before_help("Consult the help for `literate` for help with using this subcommand.")
-        .version(crate_version!());
+        .version(crate_version!())
+        .subcommand(
+            SubCommand::with_name(constants::CHECK)
+            .about("Tangle the code into a scratch copy of the workspace, and run `cargo check` on it, pointing any errors back at the `.lit` files.")
+            .arg(
+                Arg::with_name(constants::OFFLINE)
+                .help("Pass `--offline` to `cargo check`.")
+                .long("offline")
+                .required(false)));
 
     add_common_cli_options(app)
 }
@@ -86,13 +94,78 @@ fn add_common_cli_options(app: App<'static, 'static>) -> App<'static, 'static> {
             .required(false)
             .takes_value(true)
             .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::SOURCE_MAP)
+            .help("Write a `.litmap.json` source map next to each code output file.")
+            .long("source-map")
+            .required(false)
+            .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::ALLOW_OUTSIDE_OUT_DIR)
+            .help("Allow file blocks to be written outside of the output directory. Only use this for trusted .lit files.")
+            .long("allow-outside-out-dir")
+            .required(false)
+            .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::LINE_ENDINGS)
+            .help("Set the line endings of the code output - valid options are lf, crlf and native. By default, each file uses the same line endings as its .lit file.")
+            .long("line-endings")
+            .required(false)
+            .takes_value(true)
+            .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::STRIP_TRAILING_WHITESPACE)
+            .help("Strip trailing whitespace from the code output.")
+            .long("strip-trailing-whitespace")
+            .required(false)
+            .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::FINAL_NEWLINE)
+            .help("End each code output file with exactly one newline.")
+            .long("final-newline")
+            .required(false)
+            .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::BANNER)
+            .help("Put a banner at the top of each code output file, with a hash of its contents. Use `--banner=TEXT` to change the text - `%f` in it is replaced with the name of the .lit file.")
+            .long("banner")
+            .required(false)
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::FORCE)
+            .help("Overwrite code output files even if they have been edited by hand.")
+            .long("force")
+            .required(false)
+            .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::BLOCK_MARKERS)
+            .help("Put comments around every expanded block in the code output, saying where it was written.")
+            .long("block-markers")
+            .required(false)
+            .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::FORMATTER)
+            .help("Set a command to format the code output with, like `rustfmt --emit stdout`. The code is given to it on stdin, and read back from stdout.")
+            .long("formatter")
+            .required(false)
+            .takes_value(true)
+            .conflicts_with(constants::WEAVE))
+        .arg(
+            Arg::with_name(constants::UNTANGLE)
+            .help("Copy edits made to the code output back into the .lit files, instead of producing any output.")
+            .long("untangle")
+            .required(false)
+            .conflicts_with_all(&[constants::WEAVE, constants::COMPILER]))
         .arg(
             Arg::with_name(constants::WEAVE)
             .help("Only produce the documentation output.")
             .short("w")
             .long("weave"))
         .arg(Arg::with_name(constants::WEAVE_OUTPUT)
-             .help("Set the type of documentation output - valid options are markdown and html.")
+             .help("Set the type of documentation output - valid options are markdown, html, latex and mdbook.")
              .long("weave-output")
              .required(false)
              .takes_value(true)
@@ -103,6 +176,23 @@ fn add_common_cli_options(app: App<'static, 'static>) -> App<'static, 'static> {
              .required(false)
              .takes_value(true)
              .conflicts_with(constants::TANGLE))
+        .arg(Arg::with_name(constants::LATEX_CODE)
+             .help("Set the LaTeX environment that code goes in, for latex documentation output - valid options are listings and minted.")
+             .long("latex-code")
+             .required(false)
+             .takes_value(true)
+             .conflicts_with(constants::TANGLE))
+        .arg(Arg::with_name(constants::SINGLE_PAGE)
+             .help("Weave each @book and all of its chapters into a single document.")
+             .long("single-page")
+             .required(false)
+             .conflicts_with(constants::TANGLE))
+        .arg(Arg::with_name(constants::TOC)
+             .help("Set where the table of contents goes in the documentation output - valid options are top, sidebar and none. Sidebars are only supported in html.")
+             .long("toc")
+             .required(false)
+             .takes_value(true)
+             .conflicts_with(constants::TANGLE))
         .group(
             ArgGroup::with_name(constants::OUTPUT_TYPE)
             .args(&[constants::TANGLE, constants::WEAVE])
@@ -117,14 +207,39 @@ pub mod constants {
     pub const COMPILER : &'static str = "compiler";
     pub const OUTPUT_DIRECTORY : &'static str = "output_directory";
     pub const LINE_NUMBERS : &'static str = "line_numbers";
+    pub const SOURCE_MAP : &'static str = "source_map";
+    pub const UNTANGLE : &'static str = "untangle";
+    pub const ALLOW_OUTSIDE_OUT_DIR : &'static str = "allow_outside_out_dir";
+    pub const LINE_ENDINGS : &'static str = "line_endings";
+    pub const LF : &'static str = "lf";
+    pub const CRLF : &'static str = "crlf";
+    pub const NATIVE : &'static str = "native";
+    pub const STRIP_TRAILING_WHITESPACE : &'static str = "strip_trailing_whitespace";
+    pub const FINAL_NEWLINE : &'static str = "final_newline";
+    pub const BANNER : &'static str = "banner";
+    pub const FORCE : &'static str = "force";
+    pub const BLOCK_MARKERS : &'static str = "block_markers";
+    pub const FORMATTER : &'static str = "formatter";
     pub const TANGLE : &'static str = "tangle";
     pub const WEAVE : &'static str = "weave";
     pub const WEAVE_OUTPUT : &'static str = "weave_output";
     pub const HTML : &'static str = "html";
     pub const MD : &'static str = "md";
     pub const MARKDOWN : &'static str = "markdown";
+    pub const LATEX : &'static str = "latex";
+    pub const MDBOOK : &'static str = "mdbook";
     pub const MD_COMPILER : &'static str = "md_compiler";
+    pub const LATEX_CODE : &'static str = "latex_code";
+    pub const LISTINGS : &'static str = "listings";
+    pub const MINTED : &'static str = "minted";
+    pub const SINGLE_PAGE : &'static str = "single_page";
+    pub const TOC : &'static str = "toc";
+    pub const TOP : &'static str = "top";
+    pub const SIDEBAR : &'static str = "sidebar";
+    pub const NONE : &'static str = "none";
     pub const OUTPUT_TYPE : &'static str = "output_type";
+    pub const CHECK : &'static str = "check";
+    pub const OFFLINE : &'static str = "offline";
 }    
 
 pub fn input_from_args(input_path: &path::Path, args: &ArgMatches<'static>) -> rliterate_core::input::InputSettings {
@@ -141,14 +256,25 @@ pub fn output_from_args(output_dir : &path::Path, args: &ArgMatches<'static>)
     let weave = if args.is_present(constants::TANGLE) {
         info!("Setting the tangle-only flag");
         None
+    } else if args.is_present(constants::UNTANGLE) {
+        info!("Untangling, so no documentation will be produced");
+        None
     } else {
         let md_compiler = args.value_of(constants::MD_COMPILER)
             .map(|contents| contents.to_owned());
 
+        let code_environment = match args.value_of(constants::LATEX_CODE) {
+            Some(constants::LISTINGS) | None => rliterate_core::output::weave::CodeEnvironment::Listings,
+            Some(constants::MINTED) => rliterate_core::output::weave::CodeEnvironment::Minted,
+            Some(other) => return Err(rliterate_core::output::Error::BadCLIArgument(format!("Unknown LaTeX code environment: {}", other))),
+        };
+
         let weave_type = if let Some(weave_output_type) = args.value_of(constants::WEAVE_OUTPUT) {
             match weave_output_type {
                 constants::MARKDOWN | constants::MD => rliterate_core::output::weave::Type::Markdown,
                 constants::HTML => rliterate_core::output::weave::Type::HtmlViaMarkdown(md_compiler),
+                constants::LATEX => rliterate_core::output::weave::Type::Latex(code_environment),
+                constants::MDBOOK => rliterate_core::output::weave::Type::MdBook,
                 _ => return Err(rliterate_core::output::Error::BadCLIArgument(format!("Unknown documentation output type: {}", weave_output_type))),
             }
         } else {
@@ -156,10 +282,20 @@ pub fn output_from_args(output_dir : &path::Path, args: &ArgMatches<'static>)
             rliterate_core::output::weave::Type::HtmlViaMarkdown(md_compiler)
         };
 
+        let toc = match args.value_of(constants::TOC) {
+            Some(constants::TOP) => Some(rliterate_core::parser::TableOfContents::Top),
+            Some(constants::SIDEBAR) => Some(rliterate_core::parser::TableOfContents::Sidebar),
+            Some(constants::NONE) => Some(rliterate_core::parser::TableOfContents::None),
+            Some(other) => return Err(rliterate_core::output::Error::BadCLIArgument(format!("Unknown table of contents placement: {}", other))),
+            None => None,
+        };
+
         Some(rliterate_core::output::weave::Globals {
             weave_type: weave_type,
             out_dir: output_dir.to_path_buf(),
             css: rliterate_core::output::css::Globals::default(),
+            toc,
+            single_page: args.is_present(constants::SINGLE_PAGE),
         })
     };
 
@@ -170,10 +306,31 @@ pub fn output_from_args(output_dir : &path::Path, args: &ArgMatches<'static>)
         let line_number_format = args.value_of(constants::LINE_NUMBERS).map(|line_number_format_string| {
             rliterate_core::parser::generate_line_number_format(line_number_format_string)
         });
+        let line_ending = match args.value_of(constants::LINE_ENDINGS) {
+            Some(constants::LF) => Some(rliterate_core::parser::LineEnding::Lf),
+            Some(constants::CRLF) => Some(rliterate_core::parser::LineEnding::CrLf),
+            Some(constants::NATIVE) => Some(rliterate_core::parser::LineEnding::native()),
+            Some(other) => return Err(rliterate_core::output::Error::BadCLIArgument(format!("Unknown line ending: {}", other))),
+            None => None,
+        };
         Some(rliterate_core::output::tangle::Globals {
             compile: args.is_present(constants::COMPILER),
             line_numbers: line_number_format,
             out_dir: output_dir.to_path_buf(),
+            source_map: args.is_present(constants::SOURCE_MAP),
+            untangle: args.is_present(constants::UNTANGLE),
+            allow_outside_out_dir: args.is_present(constants::ALLOW_OUTSIDE_OUT_DIR),
+            line_ending,
+            strip_trailing_whitespace: args.is_present(constants::STRIP_TRAILING_WHITESPACE),
+            final_newline: args.is_present(constants::FINAL_NEWLINE),
+            banner: if args.is_present(constants::BANNER) {
+                Some(args.value_of(constants::BANNER).unwrap_or(rliterate_core::parser::DEFAULT_BANNER).to_owned())
+            } else {
+                None
+            },
+            force: args.is_present(constants::FORCE),
+            block_markers: args.is_present(constants::BLOCK_MARKERS),
+            formatter: args.value_of(constants::FORMATTER).map(|formatter| formatter.to_owned()),
         })
     };
 
@@ -25,15 +25,22 @@ extern crate url;
 use url::{Url};
 
 use std::env;
-use std::path::{Path};
+use std::path::{Path, PathBuf};
 
 use rliterate_executables::args;
+use rliterate_executables::check;
 use rliterate_core::input;
 use rliterate_core::{run};
 
-fn main() -> rliterate_core::Result<()> {
+fn main() {
     env_logger::init();
 
+    if let Err(err) = cargo_lit() {
+        rliterate_executables::exit_with_error(err);
+    }
+}
+
+fn cargo_lit() -> rliterate_core::Result<()> {
     let manifest_path = env::current_dir().ok().map(|path| path.join("Cargo.toml"));
 
     let metadata = cargo_metadata::metadata(manifest_path.as_ref().map(Path::new))
@@ -44,19 +51,41 @@ fn main() -> rliterate_core::Result<()> {
         // So we skip 'cargo', and proceed as normal
         .get_matches_from(env::args_os().skip(1));
 
+    let workspace_root = Path::new(&metadata.workspace_root);
+
+    let mut lit_folders = Vec::new();
     if metadata.workspace_members.len() == 0 {
-        run_on_workspace(&metadata.workspace_root, &args)
+        lit_folders.extend(find_lit_folders(&metadata.workspace_root)?);
     } else {
         for workspace_member in metadata.workspace_members.iter() {
             let workspace_url = Url::parse(workspace_member.url()).unwrap();
-            run_on_workspace(workspace_url.path(), &args)?; 
+            lit_folders.extend(find_lit_folders(workspace_url.path())?);
+        }
+    }
+
+    if let Some(check_args) = args.subcommand_matches(args::constants::CHECK) {
+        check::check_workspace(check::Settings {
+            workspace_root,
+            target_directory: Path::new(&metadata.target_directory),
+            lit_folders: &lit_folders,
+            offline: check_args.is_present(args::constants::OFFLINE),
+        })
+    } else {
+        for (lit_folder, output_folder) in lit_folders {
+            let input_settings = input::InputSettings::recurse(&lit_folder);
+            let output_settings = args::output_from_args(&output_folder, &args)?;
+
+            run(input_settings, output_settings)?;
         }
 
         Ok(())
     }
 }
 
-fn run_on_workspace(path: &str, args: &clap::ArgMatches<'static>) -> rliterate_core::Result<()> {
+/// Find the literate folders of a package, along with the folders that their code goes to
+fn find_lit_folders(path: &str) -> rliterate_core::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut lit_folders = Vec::new();
+
     for entry in std::fs::read_dir(path).map_err(|_| {
         rliterate_core::Error::Other(format!("Failed to read entries in `{}`", path))
     })? {
@@ -75,13 +104,9 @@ fn run_on_workspace(path: &str, args: &clap::ArgMatches<'static>) -> rliterate_c
         _ => continue,
       };
     
-      let lit_folder = entry.path();
-      let input_settings = input::InputSettings::recurse(&lit_folder);
-      let output_settings = args::output_from_args(&output_folder, &args)?;
-      
-      run(input_settings, output_settings)?;
+      lit_folders.push((entry.path(), output_folder));
     }
 
-    Ok(())
+    Ok(lit_folders)
 }
 
@@ -0,0 +1,499 @@
+/*
+ * Copyright (c) 2018 Isaac van Bakel
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of
+ * this software and associated documentation files (the "Software"), to deal in
+ * the Software without restriction, including without limitation the rights to
+ * use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+ * the Software, and to permit persons to whom the Software is furnished to do so,
+ * subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+ * FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+ * COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+ * IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ * CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! `cargo lit check`, which runs `cargo check` on freshly tangled code and points the diagnostics
+//! back at the .lit files that the code came from.
+
+use rliterate_core;
+use rliterate_core::{input, output, run};
+use rliterate_core::output::{ScratchDir};
+
+use serde_json::{self, Value};
+
+use std::collections::{HashMap};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub struct Settings<'a> {
+    pub workspace_root: &'a Path,
+    pub target_directory: &'a Path,
+    /// Pairs of literate folders and the folders that their code goes to
+    pub lit_folders: &'a [(PathBuf, PathBuf)],
+    pub offline: bool,
+}
+
+pub fn check_workspace(settings: Settings) -> rliterate_core::Result<()> {
+    let scratch = ScratchDir::new("check").map_err(|err| other_error("create a scratch directory", err))?;
+
+    trace!("Copying \"{}\" into \"{}\"...", settings.workspace_root.to_string_lossy(), scratch.path().to_string_lossy());
+    copy_tree(settings.workspace_root, settings.workspace_root, scratch.path(), &[settings.target_directory, &settings.workspace_root.join(".git")])
+        .map_err(|err| other_error("copy the workspace", err))?;
+
+    for (lit_folder, output_folder) in settings.lit_folders {
+        let relative_output = output_folder.strip_prefix(settings.workspace_root).unwrap_or(output_folder);
+
+        run(input::InputSettings::recurse(lit_folder), output::Globals {
+            generate_output: true,
+            weave: None,
+            tangle: Some(output::tangle::Globals {
+                compile: false,
+                line_numbers: None,
+                out_dir: scratch.path().join(relative_output),
+                source_map: true,
+                untangle: false,
+                allow_outside_out_dir: false,
+                line_ending: None,
+                strip_trailing_whitespace: false,
+                final_newline: false,
+                banner: None,
+                force: true,
+                block_markers: false,
+                formatter: None,
+            }),
+        })?;
+    }
+
+    let mut command = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    command.arg("check")
+        .arg("--workspace")
+        .arg("--message-format=json")
+        .arg("--manifest-path").arg(scratch.path().join("Cargo.toml"))
+        // A separate target directory keeps the checked build from clobbering the real one, while
+        // still being reused from run to run
+        .env("CARGO_TARGET_DIR", settings.target_directory.join("lit-check"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+
+    if settings.offline {
+        command.arg("--offline");
+    }
+
+    trace!("Running {:?}...", command);
+    let mut child = command.spawn().map_err(|err| other_error("run cargo check", err))?;
+
+    let mut source_maps = SourceMaps::new(scratch.path());
+    // Unwrapping is safe, because stdout was piped
+    for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+        let line = line.map_err(|err| other_error("read the output of cargo check", err))?;
+        let mut message : Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(_) => continue,
+        };
+
+        if message["reason"] == "compiler-message" {
+            let diagnostic = &mut message["message"];
+            remap_diagnostic(diagnostic, &mut source_maps);
+
+            if let Some(rendered) = diagnostic["rendered"].as_str() {
+                eprint!("{}", rendered);
+            }
+        }
+    }
+
+    let status = child.wait().map_err(|err| other_error("run cargo check", err))?;
+    trace!("Finished running cargo check");
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(rliterate_core::Error::Other("cargo check found errors in the tangled code".to_owned()))
+    }
+}
+
+fn other_error(doing: &str, err: io::Error) -> rliterate_core::Error {
+    rliterate_core::Error::Other(format!("Failed to {}: {}", doing, err))
+}
+
+fn copy_tree(root: &Path, from: &Path, to: &Path, skip: &[&Path]) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if skip.iter().any(|skipped| *skipped == path) {
+            continue;
+        }
+
+        // Symlinks are copied as links, so that a link to a directory isn't copied as if it were
+        // a directory of the workspace - or followed forever, if it points back up the tree
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            copy_symlink(root, &path, &to.join(entry.file_name()))?;
+        } else if file_type.is_dir() {
+            copy_tree(root, &path, &to.join(entry.file_name()), skip)?;
+        } else {
+            fs::copy(&path, to.join(entry.file_name()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy a symlink. A relative link to somewhere in the workspace is kept as it is, so that it
+/// points into the copy - any other link is made absolute, so that it still points at the same
+/// place from the copy.
+fn copy_symlink(root: &Path, from: &Path, to: &Path) -> io::Result<()> {
+    let target = fs::read_link(from)?;
+    // A symlink is always in some directory
+    let resolved = from.parent().unwrap().join(&target);
+
+    let target = match (resolved.canonicalize(), root.canonicalize()) {
+        (Ok(ref resolved), Ok(ref root)) if target.is_relative() && resolved.starts_with(root) => target,
+        _ => resolved,
+    };
+
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(&target, to);
+
+    #[cfg(windows)]
+    return if target.is_dir() {
+        std::os::windows::fs::symlink_dir(&target, to)
+    } else {
+        std::os::windows::fs::symlink_file(&target, to)
+    };
+}
+
+/// Where a line of tangled code came from, as recorded in its source map
+struct MappedLine {
+    source: String,
+    source_line: usize,
+    /// The column at which the .lit line's text starts in the tangled line
+    column: usize,
+}
+
+/// The source maps of the tangled files, loaded as they're needed
+struct SourceMaps<'s> {
+    scratch_root: &'s Path,
+    current_dir: Option<PathBuf>,
+    maps: HashMap<String, Option<HashMap<usize, MappedLine>>>,
+}
+
+impl<'s> SourceMaps<'s> {
+    fn new(scratch_root: &'s Path) -> Self {
+        SourceMaps {
+            scratch_root,
+            current_dir: env::current_dir().ok(),
+            maps: HashMap::new(),
+        }
+    }
+
+    fn lookup(&mut self, file_name: &str, line: usize) -> Option<&MappedLine> {
+        if !self.maps.contains_key(file_name) {
+            let map = self.load(file_name);
+            self.maps.insert(file_name.to_owned(), map);
+        }
+
+        self.maps.get(file_name)?.as_ref()?.get(&line)
+    }
+
+    fn load(&self, file_name: &str) -> Option<HashMap<usize, MappedLine>> {
+        let source_map_path = output::tangle::source_map_path(&self.scratch_root.join(file_name));
+        let source_map : Value = serde_json::from_str(&fs::read_to_string(&source_map_path).ok()?).ok()?;
+        debug!("Loaded the source map \"{}\"", source_map_path.to_string_lossy());
+
+        let lines = source_map["lines"].as_array()?.iter().filter_map(|line| {
+            let source = Path::new(line["source"].as_str()?);
+            let source = match self.current_dir {
+                Some(ref current_dir) => source.strip_prefix(current_dir).unwrap_or(source),
+                None => source,
+            };
+
+            Some((line["line"].as_u64()? as usize, MappedLine {
+                source: source.to_string_lossy().into_owned(),
+                source_line: line["source_line"].as_u64()? as usize,
+                column: line["column"].as_u64()? as usize,
+            }))
+        });
+
+        Some(lines.collect())
+    }
+
+    /// Map a tangled location to the .lit file
+    fn remap(&mut self, file_name: &str, line: usize, column: usize) -> Option<(String, usize, usize)> {
+        let mapped = self.lookup(file_name, line)?;
+        // Columns that point into text from an enclosing block get clamped to the start of the line
+        let column = if column >= mapped.column { column + 1 - mapped.column } else { 1 };
+
+        Some((mapped.source.clone(), mapped.source_line, column))
+    }
+}
+
+fn remap_diagnostic(diagnostic: &mut Value, source_maps: &mut SourceMaps) {
+    if let Some(spans) = diagnostic["spans"].as_array_mut() {
+        for span in spans {
+            remap_span(span, source_maps);
+        }
+    }
+
+    if let Some(children) = diagnostic["children"].as_array_mut() {
+        for child in children {
+            remap_diagnostic(child, source_maps);
+        }
+    }
+
+    let rendered = diagnostic["rendered"].as_str().map(|rendered| remap_rendered(rendered, source_maps));
+    if let Some(rendered) = rendered {
+        diagnostic["rendered"] = Value::String(rendered);
+    }
+}
+
+fn remap_span(span: &mut Value, source_maps: &mut SourceMaps) {
+    let location = |line_key: &str, column_key: &str| Some((
+        span["file_name"].as_str()?.to_owned(),
+        span[line_key].as_u64()? as usize,
+        span[column_key].as_u64()? as usize,
+    ));
+
+    let start = location("line_start", "column_start");
+    let end = location("line_end", "column_end");
+
+    if let Some((file_name, line, column)) = start {
+        if let Some((source, source_line, source_column)) = source_maps.remap(&file_name, line, column) {
+            span["file_name"] = Value::from(source);
+            span["line_start"] = Value::from(source_line);
+            span["column_start"] = Value::from(source_column);
+        }
+    }
+
+    if let Some((file_name, line, column)) = end {
+        if let Some((_, source_line, source_column)) = source_maps.remap(&file_name, line, column) {
+            span["line_end"] = Value::from(source_line);
+            span["column_end"] = Value::from(source_column);
+        }
+    }
+}
+
+/// Rewrite rustc's rendering of a diagnostic, so that its locations and line numbers point at
+/// the .lit file. The code snippets themselves stay as they were tangled.
+fn remap_rendered(rendered: &str, source_maps: &mut SourceMaps) -> String {
+    const ARROWS : &[&str] = &["--> ", "::: "];
+    const GUTTER_ENDS : &[&str] = &[" |", " =", "-->", ":::"];
+
+    let lines : Vec<&str> = rendered.split('\n').collect();
+
+    let arrow = |line: &str| -> Option<(usize, &'static str)> {
+        let width = line.len() - line.trim_start().len();
+        ARROWS.iter().find(|arrow| line[width..].starts_with(**arrow)).map(|arrow| (width, *arrow))
+    };
+
+    // The gutter is the column of line numbers down the left of the snippets
+    let width = match lines.iter().find_map(|line| arrow(line)) {
+        Some((width, _)) => width,
+        None => return rendered.to_owned(),
+    };
+
+    let is_gutter = |line: &str| line.len() > width
+        && line.is_char_boundary(width)
+        && line[..width].bytes().all(|byte| byte == b' ' || byte.is_ascii_digit())
+        && GUTTER_ENDS.iter().any(|end| line[width..].starts_with(end));
+
+    let mut current_file = None;
+    let mut remapped_lines = Vec::with_capacity(lines.len());
+
+    for line in lines.iter() {
+        if !is_gutter(line) {
+            remapped_lines.push((None, (*line).to_owned()));
+            continue;
+        }
+
+        let (gutter, rest) = line.split_at(width);
+
+        if let Some((_, arrow_text)) = arrow(line) {
+            let location = rest.trim_start()[arrow_text.len()..].to_owned();
+            let mut parts = location.rsplitn(3, ':');
+            let parsed = (parts.next().and_then(|c| c.parse().ok()), parts.next().and_then(|l| l.parse().ok()), parts.next());
+
+            let remapped = match parsed {
+                (Some(column), Some(line_number), Some(file_name)) => {
+                    current_file = Some(file_name.to_owned());
+                    source_maps.remap(file_name, line_number, column)
+                        .map(|(source, source_line, source_column)| format!("{}:{}:{}", source, source_line, source_column))
+                },
+                _ => None,
+            };
+
+            let arrow_line = format!("{}{}", arrow_text, remapped.unwrap_or(location));
+            remapped_lines.push((Some(String::new()), arrow_line));
+        } else {
+            let number = gutter.trim();
+            let mapped_number = if number.is_empty() {
+                String::new()
+            } else {
+                current_file.clone()
+                    .and_then(|file_name| number.parse().ok().and_then(|line_number| source_maps.lookup(&file_name, line_number)))
+                    .map_or_else(|| number.to_owned(), |mapped| mapped.source_line.to_string())
+            };
+            remapped_lines.push((Some(mapped_number), rest.to_owned()));
+        }
+    }
+
+    let new_width = remapped_lines.iter()
+        .filter_map(|(gutter, _)| gutter.as_ref().map(|gutter| gutter.len()))
+        .max()
+        .unwrap_or(0)
+        .max(width);
+
+    remapped_lines.into_iter()
+        .map(|(gutter, rest)| match gutter {
+            Some(gutter) => format!("{:>width$}{}", gutter, rest, width = new_width),
+            None => rest,
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SourceMaps, copy_tree, remap_rendered, remap_span};
+
+    use rliterate_core::output::{ScratchDir};
+    use serde_json::{self, Value};
+
+    use std::fs;
+
+    /// A scratch directory holding `src/main.rs`, whose second line came from line 12 of
+    /// `lit/main.lit` and starts at column 5
+    fn scratch() -> ScratchDir {
+        let scratch = ScratchDir::new("test").unwrap();
+        fs::create_dir(scratch.path().join("src")).unwrap();
+        fs::write(scratch.path().join("src/main.rs.litmap.json"), r#"{
+  "version": 1,
+  "file": "main.rs",
+  "lines": [
+    { "line": 2, "source": "lit/main.lit", "source_line": 12, "column": 5, "block": "body", "expansion_chain": ["main.rs", "body"] }
+  ]
+}
+"#).unwrap();
+        scratch
+    }
+
+    fn span(line: u64, column_start: u64, column_end: u64) -> Value {
+        serde_json::json!({
+            "file_name": "src/main.rs",
+            "line_start": line,
+            "line_end": line,
+            "column_start": column_start,
+            "column_end": column_end,
+        })
+    }
+
+    #[test]
+    fn spans_point_at_the_lit_file() {
+        let scratch = scratch();
+        let mut source_maps = SourceMaps::new(scratch.path());
+
+        let mut remapped = span(2, 13, 14);
+        remap_span(&mut remapped, &mut source_maps);
+
+        assert_eq!(remapped, serde_json::json!({
+            "file_name": "lit/main.lit",
+            "line_start": 12,
+            "line_end": 12,
+            "column_start": 9,
+            "column_end": 10,
+        }));
+    }
+
+    #[test]
+    fn columns_before_the_lit_text_are_clamped() {
+        let scratch = scratch();
+        let mut source_maps = SourceMaps::new(scratch.path());
+
+        let mut remapped = span(2, 1, 5);
+        remap_span(&mut remapped, &mut source_maps);
+
+        assert_eq!(remapped["column_start"], 1);
+        assert_eq!(remapped["column_end"], 1);
+    }
+
+    #[test]
+    fn unmapped_spans_are_left_alone() {
+        let scratch = scratch();
+        let mut source_maps = SourceMaps::new(scratch.path());
+
+        let mut remapped = span(3, 1, 2);
+        remap_span(&mut remapped, &mut source_maps);
+        assert_eq!(remapped, span(3, 1, 2));
+
+        let mut other_file = span(2, 1, 2);
+        other_file["file_name"] = Value::from("src/lib.rs");
+        remap_span(&mut other_file, &mut source_maps);
+        assert_eq!(other_file["file_name"], "src/lib.rs");
+    }
+
+    #[test]
+    fn rendered_diagnostics_point_at_the_lit_file() {
+        let scratch = scratch();
+        let mut source_maps = SourceMaps::new(scratch.path());
+
+        let rendered = "error[E0425]: cannot find value `x` in this scope\n \
+                        --> src/main.rs:2:13\n  \
+                        |\n\
+                        2 |     let y = x;\n  \
+                        |             ^ not found in this scope\n";
+
+        assert_eq!(remap_rendered(rendered, &mut source_maps),
+                   "error[E0425]: cannot find value `x` in this scope\n  \
+                    --> lit/main.lit:12:9\n   \
+                    |\n\
+                    12 |     let y = x;\n   \
+                    |             ^ not found in this scope\n");
+    }
+
+    #[test]
+    fn rendered_text_without_locations_is_left_alone() {
+        let scratch = scratch();
+        let mut source_maps = SourceMaps::new(scratch.path());
+
+        let rendered = "warning: 1 warning emitted\n";
+        assert_eq!(remap_rendered(rendered, &mut source_maps), rendered);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_copied_as_links() {
+        use std::os::unix::fs::symlink;
+        use std::path::{Path};
+
+        let workspace = ScratchDir::new("test").unwrap();
+        let outside = ScratchDir::new("test").unwrap();
+        let copy = ScratchDir::new("test").unwrap();
+
+        fs::create_dir(workspace.path().join("dir")).unwrap();
+        fs::write(workspace.path().join("dir/file"), "contents").unwrap();
+        symlink("dir", workspace.path().join("inside")).unwrap();
+        symlink(outside.path(), workspace.path().join("outside")).unwrap();
+        // Following this one would copy the workspace into itself forever
+        symlink("..", workspace.path().join("dir/up")).unwrap();
+
+        copy_tree(workspace.path(), workspace.path(), &copy.path().join("workspace"), &[]).unwrap();
+
+        let copied = copy.path().join("workspace");
+        assert_eq!(fs::read_to_string(copied.join("dir/file")).unwrap(), "contents");
+        assert_eq!(fs::read_link(copied.join("inside")).unwrap(), Path::new("dir"));
+        assert_eq!(fs::read_link(copied.join("outside")).unwrap(), outside.path());
+        assert_eq!(fs::read_link(copied.join("dir/up")).unwrap(), Path::new(".."));
+    }
+}
@@ -24,6 +24,33 @@ extern crate rliterate_core;
 extern crate clap;
 #[macro_use]
 extern crate log;
+extern crate serde_json;
 
 pub mod args;
+pub mod check;
+
+use rliterate_core::output::{self, CompilerOutput};
+
+/// Print an error from rliterate and exit. A compiler which failed has its output printed as it
+/// was written, rather than as part of the error.
+pub fn exit_with_error(err: rliterate_core::Error) -> ! {
+    match err {
+        rliterate_core::Error::Output(output::Error::FailedCompiler(code, compiler_output)) => {
+            print_compiler_output(&compiler_output);
+            eprintln!("Error: the compiler failed with exit code {}", code);
+        },
+        rliterate_core::Error::Output(output::Error::TerminatedCompiler(signal, compiler_output)) => {
+            print_compiler_output(&compiler_output);
+            eprintln!("Error: the compiler was terminated by signal {}", signal);
+        },
+        err => eprintln!("Error: {:?}", err),
+    }
+
+    std::process::exit(1)
+}
+
+fn print_compiler_output(compiler_output: &CompilerOutput) {
+    print!("{}", compiler_output.stdout);
+    eprint!("{}", compiler_output.stderr);
+}
 
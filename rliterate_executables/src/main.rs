@@ -27,9 +27,15 @@ use std::path;
 use rliterate_core::{run};
 use rliterate_executables::args;
 
-fn main() -> rliterate_core::Result<()> {
+fn main() {
     env_logger::init();
 
+    if let Err(err) = literate() {
+        rliterate_executables::exit_with_error(err);
+    }
+}
+
+fn literate() -> rliterate_core::Result<()> {
     let args : clap::ArgMatches<'static> = args::get_main_arg_parser().get_matches();
 
     let input_path = path::Path::new(args.value_of(args::constants::INPUT).unwrap());